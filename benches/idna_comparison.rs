@@ -1,5 +1,4 @@
 use criterion::{Criterion, black_box, criterion_group, criterion_main};
-use std::time::Duration;
 
 fn benchmark_to_ascii_ada_vs_idna(c: &mut Criterion) {
     let test_domains = vec![
@@ -148,15 +147,56 @@ fn benchmark_punycode_encoding(c: &mut Criterion) {
         b.iter(|| {
             for s in &test_strings {
                 // Convert to UTF-32 first, then encode
-                let utf32_chars = ada_idna::unicode::utf8_to_utf32(black_box(s.as_bytes()));
+                let utf32_chars =
+                    ada_idna::unicode::utf8_to_utf32(black_box(s.as_bytes())).unwrap();
                 let _ = black_box(ada_idna::punycode::utf32_to_punycode(&utf32_chars));
             }
         })
     });
 
+    // Benchmark standard idna crate
+    group.bench_function("idna_crate", |b| {
+        b.iter(|| {
+            for s in &test_strings {
+                let _ = black_box(idna::punycode::encode_str(black_box(s)));
+            }
+        })
+    });
+
     group.finish();
 }
 
+fn benchmark_punycode_encoding_by_script(c: &mut Criterion) {
+    // Labels from the CJK and Thai domains above, plus long inputs where the
+    // number of distinct code points dominates the cost.
+    let cjk_long: String = "ドメイン名例中国例子测试例え日本テスト".repeat(40);
+    let thai_long: String = "ตัวอย่างทดสอบไทย".repeat(60);
+    let inputs = [
+        ("cjk", "ドメイン名例"),
+        ("thai", "ตัวอย่าง"),
+        ("cjk_long", cjk_long.as_str()),
+        ("thai_long", thai_long.as_str()),
+    ];
+
+    for (name, input) in inputs {
+        let mut group = c.benchmark_group(format!("punycode_encode_{}", name));
+
+        group.bench_function("ada_idna", |b| {
+            let mut out = String::new();
+            b.iter(|| {
+                out.clear();
+                let _ = black_box(ada_idna::punycode::encode_str(black_box(input), &mut out));
+            })
+        });
+
+        group.bench_function("idna_crate", |b| {
+            b.iter(|| idna::punycode::encode_str(black_box(input)))
+        });
+
+        group.finish();
+    }
+}
+
 fn benchmark_punycode_decoding(c: &mut Criterion) {
     let test_punycode = vec![
         "strae-oqa",
@@ -273,6 +313,7 @@ criterion_group! {
         benchmark_to_ascii_ada_vs_idna,
         benchmark_to_unicode_ada_vs_idna,
         benchmark_punycode_encoding,
+        benchmark_punycode_encoding_by_script,
         benchmark_punycode_decoding,
         benchmark_unicode_normalization,
         benchmark_single_domain_performance,
@@ -0,0 +1,296 @@
+//! Vectorized scanning of ASCII bytes.
+//!
+//! Every scanner returns the length of the longest prefix whose bytes all
+//! satisfy a predicate. Input is processed in 8-byte SWAR words and the
+//! remainder byte by byte; with the `simd` feature, 16-byte SSE2 blocks are
+//! scanned first on x86_64. The scalar predicates below are the reference all
+//! paths are tested against.
+
+const HIGH: u64 = 0x8080_8080_8080_8080;
+
+/// ASCII code points that are forbidden in a domain besides the C0 controls
+/// and space (URL Standard, "forbidden domain code point").
+const FORBIDDEN: [u8; 14] = [
+    b'#', b'%', b'/', b':', b'<', b'>', b'?', b'@', b'[', b'\\', b']', b'^', b'|', 0x7F,
+];
+
+/// `true` for the lowercase letters, digits and hyphen of an LDH label.
+fn is_lowercase_ldh(b: u8) -> bool {
+    b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-'
+}
+
+/// `true` for bytes that are forbidden domain code points.
+fn is_forbidden(b: u8) -> bool {
+    b <= 0x20 || FORBIDDEN.contains(&b)
+}
+
+/// Length of the leading run of ASCII bytes.
+pub(crate) fn ascii_prefix_len(bytes: &[u8]) -> usize {
+    #[cfg(all(feature = "simd", target_arch = "x86_64", target_feature = "sse2"))]
+    let start = sse2::ascii_prefix_len(bytes);
+    #[cfg(not(all(feature = "simd", target_arch = "x86_64", target_feature = "sse2")))]
+    let start = 0;
+    start + swar::prefix_len(&bytes[start..], swar::non_ascii, |b| b.is_ascii())
+}
+
+/// Length of the leading run of lowercase letters, digits and hyphens.
+pub(crate) fn lowercase_ldh_prefix_len(bytes: &[u8]) -> usize {
+    #[cfg(all(feature = "simd", target_arch = "x86_64", target_feature = "sse2"))]
+    let start = sse2::lowercase_ldh_prefix_len(bytes);
+    #[cfg(not(all(feature = "simd", target_arch = "x86_64", target_feature = "sse2")))]
+    let start = 0;
+    start + swar::prefix_len(&bytes[start..], swar::not_lowercase_ldh, is_lowercase_ldh)
+}
+
+/// Length of the leading run of bytes that are not forbidden domain code
+/// points. Bytes of multi-byte UTF-8 sequences are never forbidden.
+pub(crate) fn allowed_prefix_len(bytes: &[u8]) -> usize {
+    #[cfg(all(feature = "simd", target_arch = "x86_64", target_feature = "sse2"))]
+    let start = sse2::allowed_prefix_len(bytes);
+    #[cfg(not(all(feature = "simd", target_arch = "x86_64", target_feature = "sse2")))]
+    let start = 0;
+    start + swar::prefix_len(&bytes[start..], swar::forbidden, |b| !is_forbidden(b))
+}
+
+/// Length of the leading run of ASCII code points.
+pub(crate) fn ascii_prefix_len_utf32(code_points: &[u32]) -> usize {
+    let mut pos = 0;
+    // Branch-free OR reduction that the compiler vectorizes
+    for chunk in code_points.chunks_exact(8) {
+        if chunk.iter().fold(0, |acc, &cp| acc | cp) >= 0x80 {
+            break;
+        }
+        pos += 8;
+    }
+    pos + code_points[pos..]
+        .iter()
+        .position(|&cp| cp >= 0x80)
+        .unwrap_or(code_points.len() - pos)
+}
+
+mod swar {
+    use super::HIGH;
+
+    const fn splat(b: u8) -> u64 {
+        u64::from_ne_bytes([b; 8])
+    }
+
+    /// Length of the leading run of bytes accepted by `scalar`, where `flag`
+    /// sets the high bit of every byte of a word that `scalar` rejects.
+    #[inline(always)]
+    pub(super) fn prefix_len(bytes: &[u8], flag: fn(u64) -> u64, scalar: fn(u8) -> bool) -> usize {
+        let mut pos = 0;
+        let mut chunks = bytes.chunks_exact(8);
+        for chunk in &mut chunks {
+            let word = u64::from_le_bytes(chunk.try_into().unwrap());
+            let flagged = flag(word);
+            if flagged != 0 {
+                // The first byte is the least significant one
+                return pos + flagged.trailing_zeros() as usize / 8;
+            }
+            pos += 8;
+        }
+        let rest = chunks.remainder();
+        pos + rest.iter().position(|&b| !scalar(b)).unwrap_or(rest.len())
+    }
+
+    // The helpers below take words whose high bits are clear, so no byte
+    // carries into its neighbour and every test is exact.
+
+    /// High bit set in every byte `>= lo`.
+    fn ge(x: u64, lo: u8) -> u64 {
+        x.wrapping_add(splat(0x80 - lo)) & HIGH
+    }
+
+    /// High bit set in every byte in `lo..=hi`.
+    fn between(x: u64, lo: u8, hi: u8) -> u64 {
+        ge(x, lo) & !ge(x, hi + 1)
+    }
+
+    /// High bit set in every byte equal to `c`.
+    fn eq(x: u64, c: u8) -> u64 {
+        !((x ^ splat(c)) + splat(0x7F)) & HIGH
+    }
+
+    pub(super) fn non_ascii(word: u64) -> u64 {
+        word & HIGH
+    }
+
+    pub(super) fn not_lowercase_ldh(word: u64) -> u64 {
+        let x = word & !HIGH;
+        let ldh = between(x, b'a', b'z') | between(x, b'0', b'9') | eq(x, b'-');
+        (word | !ldh) & HIGH
+    }
+
+    pub(super) fn forbidden(word: u64) -> u64 {
+        let x = word & !HIGH;
+        let forbidden = super::FORBIDDEN
+            .iter()
+            .fold(!ge(x, 0x21), |acc, &c| acc | eq(x, c));
+        forbidden & !word & HIGH
+    }
+}
+
+#[cfg(all(feature = "simd", target_arch = "x86_64", target_feature = "sse2"))]
+#[allow(unsafe_code)]
+mod sse2 {
+    use core::arch::x86_64::*;
+
+    // SAFETY (for the calls below): this module is only compiled when SSE2
+    // is enabled for the target.
+
+    pub(super) fn ascii_prefix_len(bytes: &[u8]) -> usize {
+        unsafe { ascii_blocks(bytes) }
+    }
+
+    pub(super) fn lowercase_ldh_prefix_len(bytes: &[u8]) -> usize {
+        unsafe { lowercase_ldh_blocks(bytes) }
+    }
+
+    pub(super) fn allowed_prefix_len(bytes: &[u8]) -> usize {
+        unsafe { allowed_blocks(bytes) }
+    }
+
+    /// Number of leading bytes in 16-byte blocks in which `flag` sets no
+    /// high bit.
+    #[target_feature(enable = "sse2")]
+    fn prefix_len(bytes: &[u8], flag: impl Fn(__m128i) -> __m128i) -> usize {
+        let mut pos = 0;
+        for chunk in bytes.chunks_exact(16) {
+            // SAFETY: `chunk` is 16 bytes long and `loadu` has no alignment
+            // requirement.
+            let block = unsafe { _mm_loadu_si128(chunk.as_ptr().cast()) };
+            if _mm_movemask_epi8(flag(block)) != 0 {
+                break;
+            }
+            pos += 16;
+        }
+        pos
+    }
+
+    /// All ones in every byte in `lo..=hi`. Shifting the range down to start
+    /// at -128 turns the unsigned test into one signed comparison.
+    #[target_feature(enable = "sse2")]
+    fn between(block: __m128i, lo: u8, hi: u8) -> __m128i {
+        let shifted = _mm_add_epi8(block, _mm_set1_epi8(0x80u8.wrapping_sub(lo) as i8));
+        _mm_cmplt_epi8(shifted, _mm_set1_epi8((hi - lo) as i8 - 127))
+    }
+
+    #[target_feature(enable = "sse2")]
+    fn eq(block: __m128i, c: u8) -> __m128i {
+        _mm_cmpeq_epi8(block, _mm_set1_epi8(c as i8))
+    }
+
+    #[target_feature(enable = "sse2")]
+    fn ascii_blocks(bytes: &[u8]) -> usize {
+        prefix_len(bytes, |block| block)
+    }
+
+    #[target_feature(enable = "sse2")]
+    fn lowercase_ldh_blocks(bytes: &[u8]) -> usize {
+        prefix_len(bytes, |block| {
+            let ldh = _mm_or_si128(
+                _mm_or_si128(between(block, b'a', b'z'), between(block, b'0', b'9')),
+                eq(block, b'-'),
+            );
+            _mm_xor_si128(ldh, _mm_set1_epi8(-1))
+        })
+    }
+
+    #[target_feature(enable = "sse2")]
+    fn allowed_blocks(bytes: &[u8]) -> usize {
+        prefix_len(bytes, |block| {
+            super::FORBIDDEN
+                .iter()
+                .fold(between(block, 0x00, 0x20), |acc, &c| {
+                    _mm_or_si128(acc, eq(block, c))
+                })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scalar_prefix_len(bytes: &[u8], scalar: fn(u8) -> bool) -> usize {
+        bytes
+            .iter()
+            .position(|&b| !scalar(b))
+            .unwrap_or(bytes.len())
+    }
+
+    /// Buffers of every length up to 70 filled from `pool`, each with one
+    /// byte replaced by every possible value.
+    fn for_each_buffer(mut check: impl FnMut(&[u8])) {
+        let pool = b"abcxyz0189-.AZ_";
+        let mut state = 0x1234_5678_u32;
+        for len in 0..70 {
+            let mut buffer: Vec<u8> = (0..len)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 17;
+                    state ^= state << 5;
+                    pool[state as usize % pool.len()]
+                })
+                .collect();
+            check(&buffer);
+            if len == 0 {
+                continue;
+            }
+            let at = state as usize % len;
+            for b in 0..=255 {
+                buffer[at] = b;
+                check(&buffer);
+            }
+        }
+    }
+
+    #[test]
+    fn test_scanners_match_scalar() {
+        for_each_buffer(|bytes| {
+            assert_eq!(
+                ascii_prefix_len(bytes),
+                scalar_prefix_len(bytes, |b| b.is_ascii()),
+                "{bytes:x?}"
+            );
+            assert_eq!(
+                lowercase_ldh_prefix_len(bytes),
+                scalar_prefix_len(bytes, is_lowercase_ldh),
+                "{bytes:x?}"
+            );
+            assert_eq!(
+                allowed_prefix_len(bytes),
+                scalar_prefix_len(bytes, |b| !is_forbidden(b)),
+                "{bytes:x?}"
+            );
+            let code_points: Vec<u32> = bytes.iter().map(|&b| b as u32).collect();
+            assert_eq!(
+                ascii_prefix_len_utf32(&code_points),
+                scalar_prefix_len(bytes, |b| b.is_ascii()),
+                "{bytes:x?}"
+            );
+        });
+    }
+
+    #[test]
+    fn test_swar_matches_scalar() {
+        // Exercised on its own, since SSE2 may handle most of the input
+        for_each_buffer(|bytes| {
+            for (flag, scalar) in [
+                (
+                    swar::non_ascii as fn(u64) -> u64,
+                    (|b: u8| b.is_ascii()) as fn(u8) -> bool,
+                ),
+                (swar::not_lowercase_ldh, is_lowercase_ldh),
+                (swar::forbidden, |b| !is_forbidden(b)),
+            ] {
+                assert_eq!(
+                    swar::prefix_len(bytes, flag, scalar),
+                    scalar_prefix_len(bytes, scalar),
+                    "{bytes:x?}"
+                );
+            }
+        });
+    }
+}
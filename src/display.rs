@@ -0,0 +1,324 @@
+//! Choosing between the Unicode and ASCII forms of a domain for display.
+//!
+//! Like the address bar of a browser, [`to_display_unicode`] shows a domain
+//! in Unicode only when none of its labels looks like a spoofing attempt, and
+//! falls back to the A-label form otherwise.
+
+use crate::domain::{IdnaError, to_ascii, to_unicode};
+use crate::normalization::get_ccc;
+use crate::script::{Script, ScriptSet, script_extensions};
+use crate::security::{RestrictionLevel, restriction_level};
+use crate::validation::{is_ascii, whole_script_confusables};
+
+/// Katakana that look like Latin letters, punctuation or Han ideographs
+/// (ノ, ン, ソ, ゾ).
+const KATAKANA_LOOKALIKES: [char; 4] = ['\u{30CE}', '\u{30F3}', '\u{30BD}', '\u{30BE}'];
+
+/// Marks that extend the preceding kana (ー, ゝ, ゞ, ヽ, ヾ).
+const KANA_EXTENDERS: [char; 5] = ['\u{30FC}', '\u{309D}', '\u{309E}', '\u{30FD}', '\u{30FE}'];
+
+/// The checks [`to_display_unicode`] applies to every non-ASCII label.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisplayPolicy {
+    /// Least restrictive [`RestrictionLevel`] a label may have.
+    pub max_restriction_level: RestrictionLevel,
+    /// Scripts allowed in the labels of a top-level domain, keyed by the TLD
+    /// as a U-label, e.g. `("рф", {Cyrl})`. Common and Inherited characters
+    /// are always allowed.
+    pub tld_scripts: Vec<(String, ScriptSet)>,
+    /// Whether to reject labels that have a whole-script confusable in
+    /// Latin, such as Cyrillic "сех". Labels under the TLDs listed in
+    /// `tld_scripts` are exempt.
+    pub reject_whole_script_confusables: bool,
+    /// Whether to reject the character sequences described in
+    /// [`FallbackReason::DangerousPattern`].
+    pub reject_dangerous_patterns: bool,
+}
+
+impl Default for DisplayPolicy {
+    fn default() -> Self {
+        Self {
+            max_restriction_level: RestrictionLevel::HighlyRestrictive,
+            tld_scripts: Vec::new(),
+            reject_whole_script_confusables: true,
+            reject_dangerous_patterns: true,
+        }
+    }
+}
+
+/// Why a domain is displayed in its ASCII form.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FallbackReason {
+    /// The label mixes scripts beyond
+    /// [`DisplayPolicy::max_restriction_level`].
+    MixedScript(RestrictionLevel),
+    /// The label contains a character of a script that is not allowed for
+    /// the TLD.
+    ScriptNotAllowedForTld(char),
+    /// The label is written in a script other than Latin but looks like a
+    /// Latin label.
+    WholeScriptConfusable,
+    /// The label contains a character in a position where it is commonly
+    /// used for spoofing: a combining mark at the start of the label or
+    /// repeated, a dot above after `i`, `j`, `l` or `ı`, the Katakana middle
+    /// dot, a kana extender that does not follow kana, or a Katakana letter
+    /// that looks like Latin or Han next to a non-Japanese character.
+    DangerousPattern(char),
+}
+
+/// The form in which to display a domain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DisplayDomain {
+    /// Every label is safe to show in Unicode.
+    Unicode(String),
+    /// The ASCII form, and why the label at index `label` was rejected.
+    Ascii {
+        domain: String,
+        label: usize,
+        reason: FallbackReason,
+    },
+}
+
+/// Converts `domain` to the form it should be displayed in under `policy`.
+///
+/// ASCII labels display the same either way and are not checked. Errors are
+/// those of [`to_unicode`] and [`to_ascii`].
+pub fn to_display_unicode(
+    domain: &str,
+    policy: &DisplayPolicy,
+) -> Result<DisplayDomain, IdnaError> {
+    let unicode = to_unicode(domain)?;
+    let tld = unicode.rsplit('.').next().unwrap_or_default();
+    let tld_scripts = policy
+        .tld_scripts
+        .iter()
+        .find(|(name, _)| name == tld)
+        .map(|(_, scripts)| scripts);
+
+    for (index, label) in unicode.split('.').enumerate() {
+        if is_ascii(label) {
+            continue;
+        }
+        if let Some(reason) = check_label(label, policy, tld_scripts) {
+            return Ok(DisplayDomain::Ascii {
+                domain: to_ascii(domain)?.into_owned(),
+                label: index,
+                reason,
+            });
+        }
+    }
+    Ok(DisplayDomain::Unicode(unicode))
+}
+
+fn check_label(
+    label: &str,
+    policy: &DisplayPolicy,
+    tld_scripts: Option<&ScriptSet>,
+) -> Option<FallbackReason> {
+    let level = restriction_level(label);
+    if level > policy.max_restriction_level {
+        return Some(FallbackReason::MixedScript(level));
+    }
+    if let Some(allowed) = tld_scripts
+        && let Some(c) = label.chars().find(|&c| {
+            let scripts = script_extensions(c);
+            !scripts.contains(Script::COMMON)
+                && !scripts.contains(Script::INHERITED)
+                && scripts.intersection(allowed).is_empty()
+        })
+    {
+        return Some(FallbackReason::ScriptNotAllowedForTld(c));
+    }
+    if policy.reject_whole_script_confusables
+        && tld_scripts.is_none()
+        && whole_script_confusables(label).contains(Script::LATIN)
+    {
+        return Some(FallbackReason::WholeScriptConfusable);
+    }
+    if policy.reject_dangerous_patterns
+        && let Some(c) = dangerous_pattern(label)
+    {
+        return Some(FallbackReason::DangerousPattern(c));
+    }
+    None
+}
+
+/// The first character of `label` that is part of a dangerous pattern.
+fn dangerous_pattern(label: &str) -> Option<char> {
+    let is_kana = |c: char| {
+        let scripts = script_extensions(c);
+        scripts.contains(Script::HIRAGANA) || scripts.contains(Script::KATAKANA)
+    };
+    let is_japanese = |c: char| is_kana(c) || script_extensions(c).contains(Script::HAN);
+
+    let mut previous: Option<char> = None;
+    for c in label.chars() {
+        let dangerous = match c {
+            '\u{30FB}' => true,
+            '\u{307}' if matches!(previous, Some('i' | 'j' | 'l' | '\u{131}')) => true,
+            _ if KANA_EXTENDERS.contains(&c) => !previous.is_some_and(is_kana),
+            _ if KATAKANA_LOOKALIKES.contains(&c) => previous.is_some_and(|p| !is_japanese(p)),
+            _ => get_ccc(c as u32) != 0 && previous.is_none_or(|p| p == c),
+        };
+        if dangerous {
+            return Some(c);
+        }
+        if let Some(p) = previous
+            && KATAKANA_LOOKALIKES.contains(&p)
+            && !is_japanese(c)
+        {
+            return Some(p);
+        }
+        previous = Some(c);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fallback(domain: &str, policy: &DisplayPolicy) -> Option<(usize, FallbackReason)> {
+        match to_display_unicode(domain, policy).unwrap() {
+            DisplayDomain::Unicode(_) => None,
+            DisplayDomain::Ascii { label, reason, .. } => Some((label, reason)),
+        }
+    }
+
+    #[test]
+    fn test_unicode_display() {
+        let policy = DisplayPolicy::default();
+        assert_eq!(
+            to_display_unicode("example.com", &policy).unwrap(),
+            DisplayDomain::Unicode("example.com".into())
+        );
+        assert_eq!(
+            to_display_unicode("xn--bcher-kva.example", &policy).unwrap(),
+            DisplayDomain::Unicode("b\u{fc}cher.example".into())
+        );
+        assert_eq!(
+            to_display_unicode("abc\u{65e5}\u{672c}.jp", &policy).unwrap(),
+            DisplayDomain::Unicode("abc\u{65e5}\u{672c}.jp".into())
+        );
+        assert!(to_display_unicode("a..b", &policy).is_err());
+    }
+
+    #[test]
+    fn test_mixed_script_fallback() {
+        let policy = DisplayPolicy::default();
+        let ascii = to_ascii("\u{430}pple.com").unwrap().into_owned();
+        assert_eq!(
+            to_display_unicode("\u{430}pple.com", &policy).unwrap(),
+            DisplayDomain::Ascii {
+                domain: ascii,
+                label: 0,
+                reason: FallbackReason::MixedScript(RestrictionLevel::MinimallyRestrictive),
+            }
+        );
+        let permissive = DisplayPolicy {
+            max_restriction_level: RestrictionLevel::MinimallyRestrictive,
+            ..DisplayPolicy::default()
+        };
+        assert_eq!(fallback("\u{430}pple.com", &permissive), None);
+    }
+
+    #[test]
+    fn test_tld_scripts() {
+        let policy = DisplayPolicy {
+            tld_scripts: vec![(
+                "\u{440}\u{444}".into(),
+                [Script::CYRILLIC].into_iter().collect(),
+            )],
+            ..DisplayPolicy::default()
+        };
+        assert_eq!(
+            fallback(
+                "\u{43f}\u{440}\u{438}\u{43c}\u{435}\u{440}.\u{440}\u{444}",
+                &policy
+            ),
+            None
+        );
+        assert_eq!(fallback("example.\u{440}\u{444}", &policy), None);
+        assert_eq!(
+            fallback("\u{3b1}\u{3b2}\u{3b3}.\u{440}\u{444}", &policy),
+            Some((0, FallbackReason::ScriptNotAllowedForTld('\u{3b1}')))
+        );
+        assert_eq!(fallback("\u{3bb}\u{3be}\u{3c8}.gr", &policy), None);
+    }
+
+    #[test]
+    fn test_whole_script_confusables() {
+        let policy = DisplayPolicy::default();
+        // Cyrillic "сех" and "асе"
+        assert_eq!(
+            fallback("\u{441}\u{435}\u{445}.com", &policy),
+            Some((0, FallbackReason::WholeScriptConfusable))
+        );
+        assert_eq!(
+            fallback("www.\u{430}\u{441}\u{435}.com", &policy),
+            Some((1, FallbackReason::WholeScriptConfusable))
+        );
+        assert_eq!(
+            fallback("\u{43f}\u{440}\u{438}\u{43c}\u{435}\u{440}.com", &policy),
+            None
+        );
+        let cyrillic_tld = DisplayPolicy {
+            tld_scripts: vec![(
+                "\u{440}\u{444}".into(),
+                [Script::CYRILLIC].into_iter().collect(),
+            )],
+            ..DisplayPolicy::default()
+        };
+        assert_eq!(
+            fallback("\u{441}\u{435}\u{445}.\u{440}\u{444}", &cyrillic_tld),
+            None
+        );
+    }
+
+    #[test]
+    fn test_dangerous_patterns() {
+        let policy = DisplayPolicy::default();
+        let dangerous = |domain| fallback(domain, &policy).map(|(_, reason)| reason);
+        assert_eq!(
+            dangerous("i\u{307}nstagram.com"),
+            Some(FallbackReason::DangerousPattern('\u{307}'))
+        );
+        // Leading or repeated U+0307 falls under the combining mark rule
+        assert_eq!(
+            dangerous("\u{307}abc.com"),
+            Some(FallbackReason::DangerousPattern('\u{307}'))
+        );
+        assert_eq!(
+            dangerous("a\u{307}\u{307}bc.com"),
+            Some(FallbackReason::DangerousPattern('\u{307}'))
+        );
+        assert_eq!(dangerous("a\u{307}bc.com"), None);
+        assert_eq!(
+            dangerous("example.\u{301}abc"),
+            Some(FallbackReason::DangerousPattern('\u{301}'))
+        );
+        assert_eq!(
+            dangerous("\u{65e5}\u{30fb}\u{672c}.jp"),
+            Some(FallbackReason::DangerousPattern('\u{30fb}'))
+        );
+        assert_eq!(
+            dangerous("\u{30fc}abc.jp"),
+            Some(FallbackReason::DangerousPattern('\u{30fc}'))
+        );
+        assert_eq!(
+            dangerous("abc\u{30ce}def.com"),
+            Some(FallbackReason::DangerousPattern('\u{30ce}'))
+        );
+        assert_eq!(
+            dangerous("\u{30ce}abc.com"),
+            Some(FallbackReason::DangerousPattern('\u{30ce}'))
+        );
+        assert_eq!(dangerous("\u{30ab}\u{30fc}\u{30c9}.jp"), None);
+        assert_eq!(dangerous("\u{30bb}\u{30f3}\u{30bf}\u{30fc}.jp"), None);
+        let lenient = DisplayPolicy {
+            reject_dangerous_patterns: false,
+            ..DisplayPolicy::default()
+        };
+        assert_eq!(fallback("i\u{307}nstagram.com", &lenient), None);
+    }
+}
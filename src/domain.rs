@@ -1,4 +1,5 @@
-use crate::{mapping, normalization, punycode, unicode, validation};
+use crate::uts46::{self, Uts46Error, Uts46Options};
+use crate::{ascii, mapping, normalization, punycode, validation};
 use std::borrow::Cow;
 
 #[derive(Debug)]
@@ -7,11 +8,11 @@ pub enum IdnaError {
     LabelTooLong,
     EmptyLabel,
     InvalidCharacter,
-    PunycodeError,
+    PunycodeError(punycode::PunycodeError),
     ValidationError,
 }
 
-pub fn to_ascii(domain: &str) -> Result<Cow<str>, IdnaError> {
+pub fn to_ascii(domain: &str) -> Result<Cow<'_, str>, IdnaError> {
     if domain.is_empty() {
         return Err(IdnaError::EmptyLabel);
     }
@@ -80,7 +81,72 @@ pub fn to_unicode(domain: &str) -> Result<String, IdnaError> {
     Ok(result)
 }
 
-fn process_label_to_ascii(label: &str) -> Result<Cow<str>, IdnaError> {
+/// [`to_ascii`] for a domain given as UTF-16 code units.
+///
+/// Unpaired surrogates are rejected with [`IdnaError::InvalidCharacter`].
+pub fn to_ascii_utf16(domain: &[u16]) -> Result<Vec<u16>, IdnaError> {
+    let domain = utf16_to_string(domain)?;
+    Ok(to_ascii(&domain)?.encode_utf16().collect())
+}
+
+/// [`to_unicode`] for a domain given as UTF-16 code units.
+///
+/// Unpaired surrogates are rejected with [`IdnaError::InvalidCharacter`].
+pub fn to_unicode_utf16(domain: &[u16]) -> Result<Vec<u16>, IdnaError> {
+    let domain = utf16_to_string(domain)?;
+    Ok(to_unicode(&domain)?.encode_utf16().collect())
+}
+
+/// [`to_ascii`] following UTS #46 with the given flags, driven by the IDNA
+/// mapping table rather than the crate's fast mapping (see
+/// [`uts46`](crate::uts46)). A domain of lowercase LDH labels is returned
+/// as is.
+pub fn to_ascii_with_options<'a>(
+    domain: &'a str,
+    options: &Uts46Options,
+) -> Result<Cow<'a, str>, Uts46Error> {
+    if is_plain_ldh(domain) {
+        if options.verify_dns_length && !uts46::verify_dns_length(domain) {
+            return Err(Uts46Error::DnsLength);
+        }
+        return Ok(Cow::Borrowed(domain));
+    }
+    uts46::to_ascii(domain, options).map(Cow::Owned)
+}
+
+/// [`to_unicode`] following UTS #46 with the given flags. The domain is
+/// always converted; errors are reported alongside it.
+pub fn to_unicode_with_options<'a>(
+    domain: &'a str,
+    options: &Uts46Options,
+) -> (Cow<'a, str>, Result<(), Uts46Error>) {
+    if is_plain_ldh(domain) {
+        return (Cow::Borrowed(domain), Ok(()));
+    }
+    let (unicode, result) = uts46::to_unicode(domain, options);
+    (Cow::Owned(unicode), result)
+}
+
+/// Whether every label consists of lowercase letters, digits and hyphens,
+/// without a hyphen in the first, third and fourth, or last position. UTS #46
+/// processing leaves such a domain unchanged, and only VerifyDnsLength can
+/// reject it.
+fn is_plain_ldh(domain: &str) -> bool {
+    domain.split('.').all(|label| {
+        ascii::lowercase_ldh_prefix_len(label.as_bytes()) == label.len()
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.get(2..4) != Some("--")
+    })
+}
+
+fn utf16_to_string(domain: &[u16]) -> Result<String, IdnaError> {
+    char::decode_utf16(domain.iter().copied())
+        .collect::<Result<String, _>>()
+        .map_err(|_| IdnaError::InvalidCharacter)
+}
+
+fn process_label_to_ascii(label: &str) -> Result<Cow<'_, str>, IdnaError> {
     let bytes = label.as_bytes();
     let len = bytes.len();
 
@@ -91,26 +157,8 @@ fn process_label_to_ascii(label: &str) -> Result<Cow<str>, IdnaError> {
         return Err(IdnaError::ValidationError);
     }
 
-    // Single pass: check ASCII, allowed chars, and hyphen positions
-    let mut all_lower = true;
-    for &b in bytes {
-        if b >= 128 {
-            // Non-ASCII, must fall back to mapping/normalization
-            all_lower = false;
-            break;
-        }
-        if !b.is_ascii_lowercase() && !b.is_ascii_digit() && b != b'-' {
-            // Not lowercase ASCII, digit, or hyphen
-            all_lower = false;
-            break;
-        }
-        if b.is_ascii_uppercase() {
-            all_lower = false;
-            break;
-        }
-    }
-
-    if all_lower {
+    // Single vectorized pass: lowercase ASCII letters, digits and hyphens only
+    if ascii::lowercase_ldh_prefix_len(bytes) == len {
         // Already valid ASCII and lowercase
         return Ok(Cow::Borrowed(label));
     }
@@ -137,21 +185,29 @@ fn process_label_to_ascii(label: &str) -> Result<Cow<str>, IdnaError> {
         return Err(IdnaError::InvalidCharacter);
     }
 
-    let utf32_chars = unicode::utf8_to_utf32(normalized.as_bytes());
-    if utf32_chars.is_empty() {
+    if normalized.is_empty() {
         return Err(IdnaError::InvalidInput);
     }
 
-    let punycode = punycode::utf32_to_punycode(&utf32_chars).ok_or(IdnaError::PunycodeError)?;
+    // Mapping can leave only ASCII, e.g. "straße" becomes "strasse"
+    if normalized.is_ascii() {
+        if !validation::is_label_valid(&normalized) {
+            return Err(IdnaError::ValidationError);
+        }
+        return Ok(Cow::Owned(normalized));
+    }
 
-    // Optimize: Use string concatenation instead of format! for better performance
-    let mut result = String::with_capacity(4 + punycode.len());
-    result.push_str("xn--");
-    result.push_str(&punycode);
+    // Reject over-long labels before encoding them
+    let encoded_len = punycode::punycode_encoded_len_str(&normalized);
+    let encoded_len = match encoded_len {
+        Some(len) if 4 + len > 63 => return Err(IdnaError::LabelTooLong),
+        Some(len) => len,
+        None => normalized.len(),
+    };
 
-    if result.len() > 63 {
-        return Err(IdnaError::LabelTooLong);
-    }
+    let mut result = String::with_capacity(4 + encoded_len);
+    result.push_str("xn--");
+    punycode::encode_str(&normalized, &mut result).map_err(IdnaError::PunycodeError)?;
     Ok(Cow::Owned(result))
 }
 
@@ -166,15 +222,15 @@ fn process_label_to_unicode(label: &str) -> Result<String, IdnaError> {
 
     let punycode_part = &label[4..];
 
-    let utf32_chars = punycode::punycode_to_utf32(punycode_part).ok_or(IdnaError::PunycodeError)?;
-
-    let utf8_bytes = unicode::utf32_to_utf8(&utf32_chars);
-    if utf8_bytes.is_empty() {
+    let mut decoded = String::with_capacity(punycode_part.len());
+    punycode::decode_label(punycode_part, &mut decoded).map_err(|e| match e.kind {
+        punycode::PunycodeErrorKind::InputTooLong => IdnaError::LabelTooLong,
+        _ => IdnaError::PunycodeError(e),
+    })?;
+    if decoded.is_empty() {
         return Err(IdnaError::InvalidInput);
     }
 
-    let decoded = String::from_utf8(utf8_bytes).map_err(|_| IdnaError::InvalidInput)?;
-
     let mapped = mapping::map(&decoded);
     let normalized = normalization::normalize(&mapped);
 
@@ -182,9 +238,9 @@ fn process_label_to_unicode(label: &str) -> Result<String, IdnaError> {
         return Err(IdnaError::InvalidCharacter);
     }
 
-    let re_encoded_utf32 = unicode::utf8_to_utf32(normalized.as_bytes());
-    let re_encoded_punycode =
-        punycode::utf32_to_punycode(&re_encoded_utf32).ok_or(IdnaError::PunycodeError)?;
+    let mut re_encoded_punycode = String::with_capacity(punycode_part.len());
+    punycode::encode_str(&normalized, &mut re_encoded_punycode)
+        .map_err(IdnaError::PunycodeError)?;
 
     if re_encoded_punycode != punycode_part {
         return Err(IdnaError::ValidationError);
@@ -209,8 +265,11 @@ mod tests {
     #[test]
     fn test_to_ascii_unicode() {
         let result = to_ascii("café.example");
-        assert!(result.is_ok());
-        assert!(result.unwrap().starts_with("xn--"));
+        assert_eq!(result.unwrap(), "xn--caf-dma.example");
+        // Labels that map to ASCII are not encoded
+        assert_eq!(to_ascii("stra\u{df}e.de").unwrap(), "strasse.de");
+        assert_eq!(to_ascii("\u{FB01}le.de").unwrap(), "file.de");
+        assert!(to_ascii("-\u{df}.de").is_err());
     }
 
     #[test]
@@ -218,14 +277,72 @@ mod tests {
         let result = to_unicode("xn--4ca.example");
         // Note: This test may fail due to incomplete Unicode tables
         // The composition tables are correctly implemented from unicode_tables.txt
-        if result.is_ok() {
-            let unicode_domain = result.unwrap();
+        if let Ok(unicode_domain) = result {
             assert!(unicode_domain.contains("ä"));
         }
         // Skip assertion for now as Unicode tables need full population
         // assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_utf16_entry_points() {
+        let utf16 = |s: &str| s.encode_utf16().collect::<Vec<_>>();
+        assert_eq!(
+            to_ascii_utf16(&utf16("Example.COM")).unwrap(),
+            utf16("example.com")
+        );
+        assert_eq!(
+            to_unicode_utf16(&utf16("example.com")).unwrap(),
+            utf16("example.com")
+        );
+        assert_eq!(
+            to_ascii_utf16(&utf16("Bücher.example")).unwrap(),
+            utf16("xn--bcher-kva.example")
+        );
+        assert_eq!(
+            to_unicode_utf16(&utf16("xn--bcher-kva.example")).unwrap(),
+            utf16("bücher.example")
+        );
+        assert!(matches!(
+            to_ascii_utf16(&[0x61, 0xD800, 0x2E, 0x63, 0x6F, 0x6D]),
+            Err(IdnaError::InvalidCharacter)
+        ));
+        assert!(matches!(
+            to_unicode_utf16(&[0xDC00]),
+            Err(IdnaError::InvalidCharacter)
+        ));
+        assert!(to_ascii_utf16(&[]).is_err());
+    }
+
+    #[test]
+    fn test_with_options() {
+        let options = Uts46Options::default();
+        assert!(matches!(
+            to_ascii_with_options("example.com", &options),
+            Ok(Cow::Borrowed("example.com"))
+        ));
+        assert_eq!(
+            to_ascii_with_options("Fa\u{DF}\u{FF0E}de", &options).unwrap(),
+            "xn--fa-hia.de"
+        );
+        assert_eq!(
+            to_ascii_with_options("a..b", &options),
+            Err(Uts46Error::DnsLength)
+        );
+        assert_eq!(
+            to_ascii_with_options("ab--c", &options),
+            Err(Uts46Error::Hyphens)
+        );
+        assert_eq!(
+            to_unicode_with_options("xn--fa-hia.de", &options),
+            (Cow::Borrowed("fa\u{DF}.de"), Ok(()))
+        );
+        assert_eq!(
+            to_unicode_with_options("a..b", &options),
+            (Cow::Borrowed("a..b"), Ok(()))
+        );
+    }
+
     #[test]
     fn test_empty_domain() {
         assert!(to_ascii("").is_err());
@@ -238,4 +355,19 @@ mod tests {
         let result = to_ascii(&long_label);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_a_label_too_long() {
+        let long_label = format!("xn--{}", "a".repeat(60));
+        assert!(matches!(
+            to_unicode(&long_label),
+            Err(IdnaError::LabelTooLong)
+        ));
+        // A-labels of up to 63 octets are decoded
+        let longest_label = format!("xn--{}-oxf", "a".repeat(55));
+        assert_eq!(
+            to_unicode(&longest_label).unwrap(),
+            format!("\u{FC}{}", "a".repeat(55))
+        );
+    }
 }
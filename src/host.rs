@@ -0,0 +1,427 @@
+//! The host parser of the WHATWG URL Standard.
+//!
+//! [`parse_host`] turns the host component of a URL into a domain, an IPv4 or
+//! IPv6 address, or an opaque host, failing where the standard returns failure.
+//! Validation errors that do not cause failure are not reported. The
+//! [`Display`](fmt::Display) implementation of [`Host`] is the host
+//! serializer.
+
+use crate::uts46::{self, Uts46Options};
+use crate::validation;
+use std::fmt::{self, Write};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Host {
+    /// An ASCII domain, e.g. `"xn--bcher-kva.example"`.
+    Domain(String),
+    Ipv4(u32),
+    Ipv6([u16; 8]),
+    /// The percent-encoded host of a URL with a non-special scheme.
+    Opaque(String),
+}
+
+/// Why a host failed to parse, named after the URL Standard's validation
+/// errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostError {
+    /// The host of a special URL is empty.
+    HostMissing,
+    DomainToAscii,
+    DomainToUnicode,
+    DomainInvalidCodePoint,
+    HostInvalidCodePoint,
+    Ipv4TooManyParts,
+    Ipv4NonNumericPart,
+    Ipv4OutOfRangePart,
+    Ipv6Unclosed,
+    Ipv6InvalidCompression,
+    Ipv6TooManyPieces,
+    Ipv6MultipleCompression,
+    Ipv6InvalidCodePoint,
+    Ipv6TooFewPieces,
+    Ipv4InIpv6TooManyPieces,
+    Ipv4InIpv6InvalidCodePoint,
+    Ipv4InIpv6OutOfRangePart,
+    Ipv4InIpv6TooFewParts,
+}
+
+/// Parses the host of a URL; `is_special` tells whether its scheme is a
+/// special scheme such as `http`, whose hosts are domains or addresses.
+pub fn parse_host(input: &str, is_special: bool) -> Result<Host, HostError> {
+    if let Some(address) = input.strip_prefix('[') {
+        let address = address.strip_suffix(']').ok_or(HostError::Ipv6Unclosed)?;
+        return parse_ipv6(address.as_bytes()).map(Host::Ipv6);
+    }
+    if !is_special {
+        return parse_opaque_host(input).map(Host::Opaque);
+    }
+    if input.is_empty() {
+        return Err(HostError::HostMissing);
+    }
+
+    let decoded = percent_decode(input.as_bytes());
+    let domain = String::from_utf8_lossy(&decoded);
+    let ascii_domain = url_domain_to_ascii(&domain, false)?;
+    if ends_in_a_number(&ascii_domain) {
+        return parse_ipv4(&ascii_domain).map(Host::Ipv4);
+    }
+    Ok(Host::Domain(ascii_domain))
+}
+
+impl fmt::Display for Host {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Host::Domain(host) | Host::Opaque(host) => f.write_str(host),
+            Host::Ipv4(address) => {
+                let [a, b, c, d] = address.to_be_bytes();
+                write!(f, "{a}.{b}.{c}.{d}")
+            }
+            Host::Ipv6(address) => {
+                f.write_str("[")?;
+                // The first longest run of two or more zero pieces becomes "::"
+                let mut compress = None;
+                let mut longest = 1;
+                let mut i = 0;
+                while i < 8 {
+                    let len = address[i..].iter().take_while(|&&piece| piece == 0).count();
+                    if len > longest {
+                        compress = Some(i);
+                        longest = len;
+                    }
+                    i += len.max(1);
+                }
+                let mut i = 0;
+                while i < 8 {
+                    if compress == Some(i) {
+                        f.write_str(if i == 0 { "::" } else { ":" })?;
+                        i += longest;
+                        continue;
+                    }
+                    write!(f, "{:x}", address[i])?;
+                    if i != 7 {
+                        f.write_str(":")?;
+                    }
+                    i += 1;
+                }
+                f.write_str("]")
+            }
+        }
+    }
+}
+
+/// The URL Standard's "domain to ASCII": UTS #46 ToASCII with the flags the
+/// standard sets, failing on an empty result or a forbidden domain code point.
+///
+/// `be_strict` enables UseSTD3ASCIIRules and VerifyDnsLength.
+pub fn url_domain_to_ascii(domain: &str, be_strict: bool) -> Result<String, HostError> {
+    let has_a_label = || {
+        domain
+            .split('.')
+            .any(|label| label.len() >= 4 && label[..4].eq_ignore_ascii_case("xn--"))
+    };
+    let result = if !be_strict && domain.is_ascii() && !has_a_label() {
+        domain.to_ascii_lowercase()
+    } else {
+        uts46::to_ascii(domain, &url_options(be_strict)).map_err(|_| HostError::DomainToAscii)?
+    };
+
+    if result.is_empty() {
+        return Err(HostError::DomainToAscii);
+    }
+    if validation::contains_forbidden_domain_code_point(&result) {
+        return Err(HostError::DomainInvalidCodePoint);
+    }
+    Ok(result)
+}
+
+/// The URL Standard's "domain to Unicode". The domain is always converted;
+/// a [`HostError::DomainToUnicode`] validation error is reported alongside.
+pub fn url_domain_to_unicode(domain: &str, be_strict: bool) -> (String, Result<(), HostError>) {
+    let (result, errors) = uts46::to_unicode(domain, &url_options(be_strict));
+    (result, errors.map_err(|_| HostError::DomainToUnicode))
+}
+
+fn url_options(be_strict: bool) -> Uts46Options {
+    Uts46Options {
+        check_hyphens: false,
+        check_bidi: true,
+        check_joiners: true,
+        use_std3_ascii_rules: be_strict,
+        transitional_processing: false,
+        verify_dns_length: be_strict,
+        ignore_invalid_punycode: false,
+        reject_xn_prefix: true,
+    }
+}
+
+/// Whether the last label, ignoring a trailing empty one, is a number, which
+/// makes the host an IPv4 address.
+fn ends_in_a_number(domain: &str) -> bool {
+    let mut parts: Vec<&str> = domain.split('.').collect();
+    if parts.last() == Some(&"") {
+        if parts.len() == 1 {
+            return false;
+        }
+        parts.pop();
+    }
+    let last = parts.last().copied().unwrap_or_default();
+    if !last.is_empty() && last.bytes().all(|b| b.is_ascii_digit()) {
+        return true;
+    }
+    parse_ipv4_number(last).is_some()
+}
+
+fn parse_ipv4(input: &str) -> Result<u32, HostError> {
+    let mut parts: Vec<&str> = input.split('.').collect();
+    if parts.last() == Some(&"") && parts.len() > 1 {
+        parts.pop();
+    }
+    if parts.len() > 4 {
+        return Err(HostError::Ipv4TooManyParts);
+    }
+    let numbers = parts
+        .iter()
+        .map(|part| parse_ipv4_number(part).ok_or(HostError::Ipv4NonNumericPart))
+        .collect::<Result<Vec<u64>, _>>()?;
+
+    let (&last, rest) = numbers.split_last().ok_or(HostError::Ipv4NonNumericPart)?;
+    if rest.iter().any(|&n| n > 255) || last >= 256u64.pow(5 - numbers.len() as u32) {
+        return Err(HostError::Ipv4OutOfRangePart);
+    }
+    let address = rest
+        .iter()
+        .enumerate()
+        .fold(last, |address, (i, &n)| address + (n << (8 * (3 - i))));
+    Ok(address as u32)
+}
+
+/// Parses a decimal, `0x` hexadecimal or `0` octal number. Values that do
+/// not fit are saturated, as they are out of range anyway.
+fn parse_ipv4_number(input: &str) -> Option<u64> {
+    if input.is_empty() {
+        return None;
+    }
+    let (digits, radix) =
+        if input.len() >= 2 && (input.starts_with("0x") || input.starts_with("0X")) {
+            (&input[2..], 16)
+        } else if input.len() >= 2 && input.starts_with('0') {
+            (&input[1..], 8)
+        } else {
+            (input, 10)
+        };
+    digits.chars().try_fold(0u64, |value, c| {
+        let digit = c.to_digit(radix)?;
+        Some(
+            value
+                .saturating_mul(radix as u64)
+                .saturating_add(digit as u64),
+        )
+    })
+}
+
+fn parse_ipv6(input: &[u8]) -> Result<[u16; 8], HostError> {
+    let mut address = [0u16; 8];
+    let mut piece_index = 0;
+    let mut compress = None;
+    let mut pointer = 0;
+    let c = |pointer: usize| input.get(pointer).copied();
+
+    if c(pointer) == Some(b':') {
+        if c(pointer + 1) != Some(b':') {
+            return Err(HostError::Ipv6InvalidCompression);
+        }
+        pointer += 2;
+        piece_index += 1;
+        compress = Some(piece_index);
+    }
+
+    while c(pointer).is_some() {
+        if piece_index == 8 {
+            return Err(HostError::Ipv6TooManyPieces);
+        }
+        if c(pointer) == Some(b':') {
+            if compress.is_some() {
+                return Err(HostError::Ipv6MultipleCompression);
+            }
+            pointer += 1;
+            piece_index += 1;
+            compress = Some(piece_index);
+            continue;
+        }
+
+        let mut value = 0u16;
+        let mut length = 0;
+        while length < 4 {
+            let Some(digit) = c(pointer).and_then(|b| (b as char).to_digit(16)) else {
+                break;
+            };
+            value = value * 0x10 + digit as u16;
+            pointer += 1;
+            length += 1;
+        }
+
+        match c(pointer) {
+            Some(b'.') => {
+                if length == 0 {
+                    return Err(HostError::Ipv4InIpv6InvalidCodePoint);
+                }
+                pointer -= length;
+                if piece_index > 6 {
+                    return Err(HostError::Ipv4InIpv6TooManyPieces);
+                }
+                let mut numbers_seen = 0;
+                while c(pointer).is_some() {
+                    if numbers_seen > 0 {
+                        if c(pointer) == Some(b'.') && numbers_seen < 4 {
+                            pointer += 1;
+                        } else {
+                            return Err(HostError::Ipv4InIpv6InvalidCodePoint);
+                        }
+                    }
+                    if !c(pointer).is_some_and(|b| b.is_ascii_digit()) {
+                        return Err(HostError::Ipv4InIpv6InvalidCodePoint);
+                    }
+                    let mut ipv4_piece: Option<u16> = None;
+                    while let Some(b @ b'0'..=b'9') = c(pointer) {
+                        let number = (b - b'0') as u16;
+                        ipv4_piece = match ipv4_piece {
+                            None => Some(number),
+                            Some(0) => return Err(HostError::Ipv4InIpv6InvalidCodePoint),
+                            Some(piece) => Some(piece * 10 + number),
+                        };
+                        if ipv4_piece > Some(255) {
+                            return Err(HostError::Ipv4InIpv6OutOfRangePart);
+                        }
+                        pointer += 1;
+                    }
+                    address[piece_index] =
+                        address[piece_index] * 0x100 + ipv4_piece.unwrap_or_default();
+                    numbers_seen += 1;
+                    if numbers_seen == 2 || numbers_seen == 4 {
+                        piece_index += 1;
+                    }
+                }
+                if numbers_seen != 4 {
+                    return Err(HostError::Ipv4InIpv6TooFewParts);
+                }
+                break;
+            }
+            Some(b':') => {
+                pointer += 1;
+                if c(pointer).is_none() {
+                    return Err(HostError::Ipv6InvalidCodePoint);
+                }
+            }
+            Some(_) => return Err(HostError::Ipv6InvalidCodePoint),
+            None => {}
+        }
+        address[piece_index] = value;
+        piece_index += 1;
+    }
+
+    if let Some(compress) = compress {
+        // Move the pieces after the compression to the end
+        let mut swaps = piece_index - compress;
+        piece_index = 7;
+        while piece_index != 0 && swaps > 0 {
+            address.swap(piece_index, compress + swaps - 1);
+            piece_index -= 1;
+            swaps -= 1;
+        }
+    } else if piece_index != 8 {
+        return Err(HostError::Ipv6TooFewPieces);
+    }
+    Ok(address)
+}
+
+fn parse_opaque_host(input: &str) -> Result<String, HostError> {
+    if input.bytes().any(is_forbidden_host_code_point) {
+        return Err(HostError::HostInvalidCodePoint);
+    }
+    // Percent-encode the C0 control percent-encode set
+    let mut result = String::with_capacity(input.len());
+    for &b in input.as_bytes() {
+        if !(0x20..=0x7E).contains(&b) {
+            let _ = write!(result, "%{b:02X}");
+        } else {
+            result.push(b as char);
+        }
+    }
+    Ok(result)
+}
+
+fn is_forbidden_host_code_point(b: u8) -> bool {
+    matches!(
+        b,
+        0x00 | b'\t'
+            | b'\n'
+            | b'\r'
+            | b' '
+            | b'#'
+            | b'/'
+            | b':'
+            | b'<'
+            | b'>'
+            | b'?'
+            | b'@'
+            | b'['
+            | b'\\'
+            | b']'
+            | b'^'
+            | b'|'
+    )
+}
+
+fn percent_decode(input: &[u8]) -> Vec<u8> {
+    let hex = |b: Option<&u8>| b.and_then(|&b| (b as char).to_digit(16));
+    let mut output = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        if input[i] == b'%'
+            && let (Some(high), Some(low)) = (hex(input.get(i + 1)), hex(input.get(i + 2)))
+        {
+            output.push((high * 16 + low) as u8);
+            i += 3;
+            continue;
+        }
+        output.push(input[i]);
+        i += 1;
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ipv4_number() {
+        assert_eq!(parse_ipv4_number("255"), Some(255));
+        assert_eq!(parse_ipv4_number("0x"), Some(0));
+        assert_eq!(parse_ipv4_number("0XfF"), Some(255));
+        assert_eq!(parse_ipv4_number("0377"), Some(255));
+        assert_eq!(parse_ipv4_number("08"), None);
+        assert_eq!(parse_ipv4_number(""), None);
+        assert_eq!(parse_ipv4_number("99999999999999999999999"), Some(u64::MAX));
+        assert!(ends_in_a_number("example.0x"));
+        assert!(ends_in_a_number("1.2.3."));
+        assert!(!ends_in_a_number("example.com"));
+        assert!(!ends_in_a_number("."));
+    }
+
+    #[test]
+    fn test_serialize() {
+        let ipv6 = |address| Host::Ipv6(address).to_string();
+        assert_eq!(ipv6([0; 8]), "[::]");
+        assert_eq!(ipv6([1, 0, 0, 0, 0, 0, 0, 0]), "[1::]");
+        assert_eq!(ipv6([1, 0, 0, 2, 0, 0, 0, 3]), "[1:0:0:2::3]");
+        // A single zero piece is not compressed
+        assert_eq!(ipv6([1, 0, 2, 3, 4, 5, 6, 7]), "[1:0:2:3:4:5:6:7]");
+        assert_eq!(
+            ipv6([0x2001, 0xdb8, 0, 0, 1, 0, 0, 1]),
+            "[2001:db8::1:0:0:1]"
+        );
+        assert_eq!(Host::Ipv4(0xC0A8_0001).to_string(), "192.168.0.1");
+        assert_eq!(Host::Opaque("%C3%B1".into()).to_string(), "%C3%B1");
+    }
+}
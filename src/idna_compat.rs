@@ -0,0 +1,374 @@
+//! The public API of the `idna` crate (version 1.0), for dependents such as
+//! `url` that are written against it.
+//!
+//! The functions keep the signatures and the success and failure outcomes
+//! of their `idna` counterparts. They are backed by
+//! [`to_ascii_with_options`](crate::to_ascii_with_options) and
+//! [`to_unicode_with_options`](crate::to_unicode_with_options), since the
+//! fast mapping of [`to_ascii`](crate::to_ascii) does not reproduce UTS #46.
+//! One difference remains: when ToUnicode fails, `idna` marks the offending
+//! positions with U+FFFD, while the domain returned here is left as
+//! processed.
+
+use std::borrow::Cow;
+use std::fmt;
+
+pub use self::uts46::AsciiDenyList;
+use self::uts46::{DnsLength, Hyphens, Uts46};
+
+/// The error of every operation. Like in `idna`, it carries no detail.
+#[derive(Default, Debug)]
+#[non_exhaustive]
+pub struct Errors {}
+
+impl From<Errors> for Result<(), Errors> {
+    fn from(e: Errors) -> Result<(), Errors> {
+        Err(e)
+    }
+}
+
+impl std::error::Error for Errors {}
+
+impl fmt::Display for Errors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// ToASCII with the given deny list, hyphens allowed and no length checks.
+/// This is what the URL Standard uses, with [`AsciiDenyList::URL`].
+pub fn domain_to_ascii_cow(
+    domain: &[u8],
+    ascii_deny_list: AsciiDenyList,
+) -> Result<Cow<'_, str>, Errors> {
+    Uts46::new().to_ascii(domain, ascii_deny_list, Hyphens::Allow, DnsLength::Ignore)
+}
+
+/// ToASCII without a deny list.
+pub fn domain_to_ascii(domain: &str) -> Result<String, Errors> {
+    domain_to_ascii_cow(domain.as_bytes(), AsciiDenyList::EMPTY).map(Cow::into_owned)
+}
+
+/// ToASCII with every check: STD3 rules, hyphens and DNS lengths.
+pub fn domain_to_ascii_strict(domain: &str) -> Result<String, Errors> {
+    Uts46::new()
+        .to_ascii(
+            domain.as_bytes(),
+            AsciiDenyList::STD3,
+            Hyphens::Check,
+            DnsLength::Verify,
+        )
+        .map(Cow::into_owned)
+}
+
+/// ToUnicode without a deny list.
+pub fn domain_to_unicode(domain: &str) -> (String, Result<(), Errors>) {
+    let (domain, result) =
+        Uts46::new().to_unicode(domain.as_bytes(), AsciiDenyList::EMPTY, Hyphens::Allow);
+    (domain.into_owned(), result)
+}
+
+/// The flags of the `idna` 0.5 API, which `idna` 1.0 keeps as deprecated.
+/// Unlike [`domain_to_ascii_strict`], `check_hyphens` only checks the first
+/// and last positions and `verify_dns_length` allows the root dot.
+#[derive(Debug, Clone, Copy, Default)]
+#[must_use]
+pub struct Config {
+    use_std3_ascii_rules: bool,
+    transitional_processing: bool,
+    verify_dns_length: bool,
+    check_hyphens: bool,
+}
+
+impl Config {
+    pub fn use_std3_ascii_rules(mut self, value: bool) -> Self {
+        self.use_std3_ascii_rules = value;
+        self
+    }
+
+    pub fn transitional_processing(mut self, value: bool) -> Self {
+        self.transitional_processing = value;
+        self
+    }
+
+    pub fn verify_dns_length(mut self, value: bool) -> Self {
+        self.verify_dns_length = value;
+        self
+    }
+
+    pub fn check_hyphens(mut self, value: bool) -> Self {
+        self.check_hyphens = value;
+        self
+    }
+
+    /// # Panics
+    ///
+    /// If `value` is true: IDNA 2008 rules are not supported, as in `idna`.
+    pub fn use_idna_2008_rules(self, value: bool) -> Self {
+        assert!(!value, "IDNA 2008 rules are no longer supported");
+        self
+    }
+
+    pub fn to_ascii(self, domain: &str) -> Result<String, Errors> {
+        let dns_length = if self.verify_dns_length {
+            DnsLength::VerifyAllowRootDot
+        } else {
+            DnsLength::Ignore
+        };
+        Uts46::new()
+            .to_ascii(
+                self.map_transitional(domain).as_bytes(),
+                self.deny_list(),
+                self.hyphens(),
+                dns_length,
+            )
+            .map(Cow::into_owned)
+    }
+
+    pub fn to_unicode(self, domain: &str) -> (String, Result<(), Errors>) {
+        let mapped = self.map_transitional(domain);
+        let (domain, result) =
+            Uts46::new().to_unicode(mapped.as_bytes(), self.deny_list(), self.hyphens());
+        (domain.into_owned(), result)
+    }
+
+    fn deny_list(&self) -> AsciiDenyList {
+        if self.use_std3_ascii_rules {
+            AsciiDenyList::STD3
+        } else {
+            AsciiDenyList::EMPTY
+        }
+    }
+
+    fn hyphens(&self) -> Hyphens {
+        if self.check_hyphens {
+            Hyphens::CheckFirstLast
+        } else {
+            Hyphens::Allow
+        }
+    }
+
+    /// Like `idna`, transitional processing only replaces the deviation
+    /// characters of the input, not those of decoded A-labels.
+    fn map_transitional<'a>(&self, domain: &'a str) -> Cow<'a, str> {
+        let is_deviation = |c| {
+            matches!(
+                c,
+                '\u{DF}' | '\u{1E9E}' | '\u{3C2}' | '\u{200C}' | '\u{200D}'
+            )
+        };
+        if !self.transitional_processing || !domain.contains(is_deviation) {
+            return Cow::Borrowed(domain);
+        }
+        let mut mapped = String::with_capacity(domain.len());
+        for c in domain.chars() {
+            match c {
+                '\u{DF}' | '\u{1E9E}' => mapped.push_str("ss"),
+                '\u{3C2}' => mapped.push('\u{3C3}'),
+                '\u{200C}' | '\u{200D}' => {}
+                _ => mapped.push(c),
+            }
+        }
+        Cow::Owned(mapped)
+    }
+}
+
+/// The `idna::uts46` module: UTS #46 processing with the options spelled
+/// out.
+pub mod uts46 {
+    use super::Errors;
+    use crate::domain;
+    use crate::punycode::PUNYCODE;
+    use crate::uts46::Uts46Options;
+    use std::borrow::Cow;
+
+    /// ASCII code points that make processing fail when they occur in a
+    /// label, in addition to upper case letters, which are lowercased.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct AsciiDenyList {
+        bits: u128,
+    }
+
+    impl AsciiDenyList {
+        /// Denies the code points of `deny_list`, and with `deny_glyphless`
+        /// also U+0000 to U+0020 and U+007F.
+        ///
+        /// # Panics
+        ///
+        /// If `deny_list` contains a letter, digit, hyphen, full stop or
+        /// non-ASCII code point.
+        pub const fn new(deny_glyphless: bool, deny_list: &str) -> Self {
+            let mut bits = 0;
+            if deny_glyphless {
+                bits |= (1 << 0x21) - 1;
+                bits |= 1 << 0x7F;
+            }
+            let bytes = deny_list.as_bytes();
+            let mut i = 0;
+            while i < bytes.len() {
+                let b = bytes[i];
+                assert!(b.is_ascii(), "ASCII deny list must be ASCII.");
+                assert!(
+                    !b.is_ascii_alphanumeric() && b != b'-' && b != b'.',
+                    "ASCII deny list must not contain letters, digits, the hyphen or the dot."
+                );
+                bits |= 1 << b;
+                i += 1;
+            }
+            AsciiDenyList { bits }
+        }
+
+        /// No deny list: UseSTD3ASCIIRules=false.
+        pub const EMPTY: AsciiDenyList = AsciiDenyList::new(false, "");
+
+        /// Everything but lowercase letters, digits and the hyphen:
+        /// UseSTD3ASCIIRules=true.
+        pub const STD3: AsciiDenyList =
+            AsciiDenyList::new(true, "!\"#$%&'()*+,/:;<=>?@[\\]^_`{|}~");
+
+        /// The forbidden domain code points of the URL Standard.
+        pub const URL: AsciiDenyList = AsciiDenyList::new(true, "%#/:<>?@[\\]^|");
+
+        fn denies(&self, c: char) -> bool {
+            c.is_ascii() && self.bits & (1 << c as u32) != 0
+        }
+    }
+
+    /// The CheckHyphens flag, with a mode that checks only the first and
+    /// last positions.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[non_exhaustive]
+    pub enum Hyphens {
+        Allow,
+        CheckFirstLast,
+        Check,
+    }
+
+    /// The VerifyDNSLength flag, with a mode that allows the root dot.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[non_exhaustive]
+    pub enum DnsLength {
+        Ignore,
+        VerifyAllowRootDot,
+        Verify,
+    }
+
+    /// Checks the lengths of an ASCII domain, with or without a trailing
+    /// root dot.
+    pub fn verify_dns_length(domain_name: &str, allow_trailing_dot: bool) -> bool {
+        let domain = match domain_name.strip_suffix('.') {
+            Some(domain) if allow_trailing_dot => domain,
+            Some(_) => return false,
+            None => domain_name,
+        };
+        domain.len() <= 253
+            && domain
+                .split('.')
+                .all(|label| !label.is_empty() && label.len() <= 63)
+    }
+
+    /// UTS #46 processing, through
+    /// [`domain::to_ascii_with_options`] and
+    /// [`domain::to_unicode_with_options`].
+    #[derive(Debug, Default)]
+    pub struct Uts46 {}
+
+    impl Uts46 {
+        pub const fn new() -> Self {
+            Uts46 {}
+        }
+
+        /// ToASCII. Input that is not UTF-8 fails.
+        pub fn to_ascii<'a>(
+            &self,
+            domain_name: &'a [u8],
+            ascii_deny_list: AsciiDenyList,
+            hyphens: Hyphens,
+            dns_length: DnsLength,
+        ) -> Result<Cow<'a, str>, Errors> {
+            // Ill-formed UTF-8 becomes U+FFFD, which is disallowed
+            let input = String::from_utf8_lossy(domain_name);
+            let domain = domain::to_ascii_with_options(&input, &options(hyphens))
+                .map_err(|_| Errors::default())?;
+            // The deny list and the hyphen mode apply to the labels in
+            // Unicode form
+            for label in domain.split('.') {
+                let valid = match label.strip_prefix("xn--") {
+                    Some(encoded) => {
+                        let mut decoded = String::new();
+                        PUNYCODE.decode_to_string(encoded, &mut decoded).is_ok()
+                            && passes(&decoded, ascii_deny_list, hyphens)
+                    }
+                    None => passes(label, ascii_deny_list, hyphens),
+                };
+                if !valid {
+                    return Err(Errors::default());
+                }
+            }
+            let length_ok = match dns_length {
+                DnsLength::Ignore => true,
+                DnsLength::VerifyAllowRootDot => verify_dns_length(&domain, true),
+                DnsLength::Verify => verify_dns_length(&domain, false),
+            };
+            if !length_ok {
+                return Err(Errors::default());
+            }
+            Ok(borrow_if_unchanged(domain_name, domain.into_owned()))
+        }
+
+        /// ToUnicode. The domain is always converted; failure is reported
+        /// alongside it.
+        pub fn to_unicode<'a>(
+            &self,
+            domain_name: &'a [u8],
+            ascii_deny_list: AsciiDenyList,
+            hyphens: Hyphens,
+        ) -> (Cow<'a, str>, Result<(), Errors>) {
+            let input = String::from_utf8_lossy(domain_name);
+            let (domain, result) = domain::to_unicode_with_options(&input, &options(hyphens));
+            let result = match result {
+                Ok(())
+                    if domain
+                        .split('.')
+                        .all(|label| passes(label, ascii_deny_list, hyphens)) =>
+                {
+                    Ok(())
+                }
+                _ => Err(Errors::default()),
+            };
+            (
+                borrow_if_unchanged(domain_name, domain.into_owned()),
+                result,
+            )
+        }
+    }
+
+    /// CheckBidi and CheckJoiners are always on and processing is
+    /// nontransitional. Like `idna`, a label may begin with `xn--` after
+    /// conversion.
+    fn options(hyphens: Hyphens) -> Uts46Options {
+        Uts46Options {
+            check_hyphens: hyphens == Hyphens::Check,
+            check_bidi: true,
+            check_joiners: true,
+            use_std3_ascii_rules: false,
+            transitional_processing: false,
+            verify_dns_length: false,
+            ignore_invalid_punycode: false,
+            reject_xn_prefix: false,
+        }
+    }
+
+    /// The checks of a label in Unicode form that UTS #46 leaves out.
+    fn passes(label: &str, ascii_deny_list: AsciiDenyList, hyphens: Hyphens) -> bool {
+        !(hyphens == Hyphens::CheckFirstLast && (label.starts_with('-') || label.ends_with('-')))
+            && !label.chars().any(|c| ascii_deny_list.denies(c))
+    }
+
+    fn borrow_if_unchanged(domain_name: &[u8], domain: String) -> Cow<'_, str> {
+        match std::str::from_utf8(domain_name) {
+            Ok(input) if input == domain => Cow::Borrowed(input),
+            _ => Cow::Owned(domain),
+        }
+    }
+}
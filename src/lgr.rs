@@ -0,0 +1,1080 @@
+//! Label Generation Rulesets (RFC 7940).
+//!
+//! An [`Lgr`] is read from the XML format of RFC 7940 and evaluates U-labels
+//! against its repertoire, the context rules (`when` and `not-when`) of each
+//! code point, and its whole-label evaluation actions. It also generates the
+//! variant labels of a label from the variant mappings of its repertoire.
+//!
+//! Classes may be defined by code points, tags, the `ccc` (canonical
+//! combining class) property and the set operators. Other Unicode
+//! properties are rejected with [`LgrError::Unsupported`].
+
+use crate::domain::{IdnaError, to_unicode};
+use crate::normalization::get_ccc;
+use crate::punycode::{PunycodeError, utf32_to_punycode};
+use crate::xml::{self, Element};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum LgrError {
+    /// The file could not be read.
+    Io(std::io::Error),
+    /// The document is not well-formed XML; the byte offset of the error.
+    Xml(usize),
+    /// The document is not a valid LGR, e.g. a rule refers to an undefined
+    /// class.
+    Invalid(String),
+    /// The document uses a feature this implementation does not support.
+    Unsupported(String),
+}
+
+/// The disposition of a label, set by the action that triggered for it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Disposition {
+    Valid,
+    Invalid,
+    Blocked,
+    Allocatable,
+    /// A disposition defined by the LGR itself.
+    Other(String),
+}
+
+impl Disposition {
+    fn parse(value: &str) -> Self {
+        match value {
+            "valid" => Disposition::Valid,
+            "invalid" => Disposition::Invalid,
+            "blocked" => Disposition::Blocked,
+            "allocatable" => Disposition::Allocatable,
+            _ => Disposition::Other(value.to_string()),
+        }
+    }
+}
+
+/// Why a label received its disposition.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reason {
+    /// The code point at byte offset `position` is not in the repertoire.
+    NotInRepertoire { position: usize, code_point: char },
+    /// The context rule of the code point at byte offset `position` is not
+    /// satisfied.
+    ContextRule { position: usize, rule: String },
+    /// The action with this index among the `<action>` elements triggered.
+    Action(usize),
+    /// No action of the LGR triggered, and one of the default actions of
+    /// RFC 7940 applied.
+    Default,
+}
+
+/// The result of evaluating a label against an [`Lgr`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Evaluation {
+    pub disposition: Disposition,
+    pub reason: Reason,
+}
+
+/// A variant label produced by [`Lgr::variants`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VariantLabel {
+    pub u_label: String,
+    pub a_label: String,
+    pub disposition: Disposition,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VariantError {
+    /// The label itself is not valid under the LGR.
+    InvalidLabel(Evaluation),
+    /// The label has more variant labels than the limit.
+    TooManyVariants,
+    /// A variant label cannot be encoded as an A-label.
+    Punycode(PunycodeError),
+}
+
+/// A Label Generation Ruleset.
+#[derive(Debug)]
+pub struct Lgr {
+    /// Code points and sequences, keyed by their code points.
+    sequences: HashMap<Vec<u32>, Entry>,
+    longest_sequence: usize,
+    /// Ranges of code points sharing an entry, sorted.
+    ranges: Vec<(u32, u32, Entry)>,
+    rules: Vec<Pattern>,
+    rule_names: Vec<String>,
+    actions: Vec<Action>,
+}
+
+#[derive(Debug, Default)]
+struct Entry {
+    when: Option<usize>,
+    not_when: Option<usize>,
+    variants: Vec<Variant>,
+}
+
+#[derive(Debug)]
+struct Variant {
+    code_points: Vec<u32>,
+    kind: String,
+    when: Option<usize>,
+    not_when: Option<usize>,
+}
+
+#[derive(Debug)]
+struct Action {
+    disposition: Disposition,
+    condition: Condition,
+}
+
+#[derive(Debug)]
+enum Condition {
+    Always,
+    Match(usize),
+    NotMatch(usize),
+    /// Some code point has a variant type in the list.
+    AnyVariant(Vec<String>),
+    /// At least one code point has a variant type, and all of them are in
+    /// the list.
+    AllVariants(Vec<String>),
+    /// Every code point has a variant type in the list.
+    OnlyVariants(Vec<String>),
+}
+
+/// A repertoire entry matched in a label, with the index and length of the
+/// code points it matched.
+struct Segment<'a> {
+    start: usize,
+    len: usize,
+    entry: &'a Entry,
+}
+
+impl Lgr {
+    /// Reads an LGR from an XML file.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Lgr, LgrError> {
+        let document = std::fs::read_to_string(path).map_err(LgrError::Io)?;
+        Lgr::parse(&document)
+    }
+
+    /// Parses an LGR from its XML representation.
+    pub fn parse(document: &str) -> Result<Lgr, LgrError> {
+        let root = xml::parse(document).map_err(LgrError::Xml)?;
+        if root.name != "lgr" {
+            return Err(LgrError::Invalid("the root element is not <lgr>".into()));
+        }
+        let data = root
+            .child("data")
+            .ok_or_else(|| LgrError::Invalid("missing <data>".into()))?;
+        let no_rules = Element::default();
+        let rules = root.child("rules").unwrap_or(&no_rules);
+        Builder::new(data, rules)?.build(data, rules)
+    }
+
+    /// Evaluates a U-label, such as a label of the output of
+    /// [`to_unicode`].
+    pub fn evaluate(&self, label: &str) -> Evaluation {
+        let code_points: Vec<u32> = label.chars().map(u32::from).collect();
+        match self.segments(label, &code_points) {
+            Ok(segments) => {
+                let kinds: Vec<Option<&str>> = segments
+                    .iter()
+                    .map(|segment| self.reflexive_kind(segment, &code_points))
+                    .collect();
+                self.evaluate_actions(&code_points, &kinds)
+            }
+            Err(evaluation) => evaluation,
+        }
+    }
+
+    /// Enumerates the variant labels of the U-label `label` with their
+    /// A-labels and dispositions, in a stable order. The label itself is not
+    /// included.
+    ///
+    /// Each repertoire entry of the label is replaced by each of its variants
+    /// whose context rules hold in the label, so the number of variant labels
+    /// grows exponentially with the label's length. If there would be more
+    /// than `limit`, none are generated.
+    pub fn variants(&self, label: &str, limit: usize) -> Result<Vec<VariantLabel>, VariantError> {
+        let code_points: Vec<u32> = label.chars().map(u32::from).collect();
+        let segments = self
+            .segments(label, &code_points)
+            .map_err(VariantError::InvalidLabel)?;
+
+        // The replacements of every segment, the original code points first
+        let choices: Vec<Vec<(&[u32], Option<&str>)>> = segments
+            .iter()
+            .map(|segment| {
+                let source = &code_points[segment.start..segment.start + segment.len];
+                let context = Context {
+                    label: &code_points,
+                    anchor: Some((segment.start, segment.len)),
+                };
+                let mut choices = vec![(source, self.reflexive_kind(segment, &code_points))];
+                choices.extend(
+                    segment
+                        .entry
+                        .variants
+                        .iter()
+                        .filter(|variant| {
+                            variant.code_points != source
+                                && self
+                                    .failed_context_rule(variant.when, variant.not_when, &context)
+                                    .is_none()
+                        })
+                        .map(|variant| (&variant.code_points[..], Some(variant.kind.as_str()))),
+                );
+                choices
+            })
+            .collect();
+
+        let count = choices
+            .iter()
+            .try_fold(1usize, |count, choices| count.checked_mul(choices.len()))
+            .map(|count| count - 1);
+        match count {
+            Some(count) if count <= limit => {}
+            _ => return Err(VariantError::TooManyVariants),
+        }
+
+        let mut variants = Vec::new();
+        let mut indices = vec![0; choices.len()];
+        // Advance like an odometer, skipping the original label
+        while let Some(position) = indices
+            .iter()
+            .zip(&choices)
+            .rposition(|(&index, choices)| index + 1 < choices.len())
+        {
+            indices[position] += 1;
+            indices[position + 1..].fill(0);
+
+            let mut variant = Vec::with_capacity(code_points.len());
+            let mut kinds = Vec::with_capacity(choices.len());
+            for (&index, choices) in indices.iter().zip(&choices) {
+                let (code_points, kind) = choices[index];
+                variant.extend_from_slice(code_points);
+                kinds.push(kind);
+            }
+            let u_label: String = variant
+                .iter()
+                .filter_map(|&cp| char::from_u32(cp))
+                .collect();
+            let a_label = if u_label.is_ascii() {
+                u_label.clone()
+            } else {
+                format!(
+                    "xn--{}",
+                    utf32_to_punycode(&variant).map_err(VariantError::Punycode)?
+                )
+            };
+            variants.push(VariantLabel {
+                u_label,
+                a_label,
+                disposition: self.evaluate_actions(&variant, &kinds).disposition,
+            });
+        }
+        Ok(variants)
+    }
+
+    /// Converts `label` with [`to_unicode`], so that it may be an A-label,
+    /// and evaluates the result.
+    pub fn evaluate_label(&self, label: &str) -> Result<Evaluation, IdnaError> {
+        if label.contains('.') {
+            return Err(IdnaError::InvalidInput);
+        }
+        Ok(self.evaluate(&to_unicode(label)?))
+    }
+
+    /// Splits a label into repertoire entries and checks their context
+    /// rules, or returns why the label is invalid.
+    fn segments(&self, label: &str, code_points: &[u32]) -> Result<Vec<Segment<'_>>, Evaluation> {
+        let offsets: Vec<usize> = label.char_indices().map(|(i, _)| i).collect();
+        let invalid = |reason| Evaluation {
+            disposition: Disposition::Invalid,
+            reason,
+        };
+
+        let mut segments = Vec::new();
+        let mut start = 0;
+        while start < code_points.len() {
+            let Some((len, entry)) = self.lookup(&code_points[start..]) else {
+                return Err(invalid(Reason::NotInRepertoire {
+                    position: offsets[start],
+                    code_point: char::from_u32(code_points[start]).unwrap_or_default(),
+                }));
+            };
+            segments.push(Segment { start, len, entry });
+            start += len;
+        }
+
+        for segment in &segments {
+            let context = Context {
+                label: code_points,
+                anchor: Some((segment.start, segment.len)),
+            };
+            if let Some(rule) =
+                self.failed_context_rule(segment.entry.when, segment.entry.not_when, &context)
+            {
+                return Err(invalid(Reason::ContextRule {
+                    position: offsets[segment.start],
+                    rule: self.rule_names[rule].clone(),
+                }));
+            }
+        }
+        Ok(segments)
+    }
+
+    /// The variant type of an unchanged entry, given by a reflexive variant
+    /// that maps it to itself.
+    fn reflexive_kind<'a>(&'a self, segment: &Segment<'a>, code_points: &[u32]) -> Option<&'a str> {
+        let source = &code_points[segment.start..segment.start + segment.len];
+        let context = Context {
+            label: code_points,
+            anchor: Some((segment.start, segment.len)),
+        };
+        segment
+            .entry
+            .variants
+            .iter()
+            .find(|variant| {
+                variant.code_points == source
+                    && self
+                        .failed_context_rule(variant.when, variant.not_when, &context)
+                        .is_none()
+            })
+            .map(|variant| variant.kind.as_str())
+    }
+
+    /// The repertoire entry for the longest sequence at the start of
+    /// `code_points`, and its length.
+    fn lookup(&self, code_points: &[u32]) -> Option<(usize, &Entry)> {
+        for len in (1..=self.longest_sequence.min(code_points.len())).rev() {
+            if let Some(entry) = self.sequences.get(&code_points[..len]) {
+                return Some((len, entry));
+            }
+        }
+        let cp = code_points[0];
+        let i = self.ranges.partition_point(|&(_, last, _)| last < cp);
+        match self.ranges.get(i) {
+            Some((first, _, entry)) if *first <= cp => Some((1, entry)),
+            _ => None,
+        }
+    }
+
+    /// The `when` or `not-when` rule that `context` does not satisfy.
+    fn failed_context_rule(
+        &self,
+        when: Option<usize>,
+        not_when: Option<usize>,
+        context: &Context<'_>,
+    ) -> Option<usize> {
+        if let Some(rule) = when
+            && !self.rules[rule].matches(context)
+        {
+            return Some(rule);
+        }
+        if let Some(rule) = not_when
+            && self.rules[rule].matches(context)
+        {
+            return Some(rule);
+        }
+        None
+    }
+
+    /// Applies the actions to a label whose repertoire entries have the
+    /// given variant types.
+    fn evaluate_actions(&self, code_points: &[u32], kinds: &[Option<&str>]) -> Evaluation {
+        let context = Context {
+            label: code_points,
+            anchor: None,
+        };
+        let any = |list: &[String]| {
+            kinds
+                .iter()
+                .flatten()
+                .any(|kind| list.iter().any(|k| k == kind))
+        };
+        let all = |list: &[String]| {
+            kinds.iter().any(Option::is_some)
+                && kinds
+                    .iter()
+                    .flatten()
+                    .all(|kind| list.iter().any(|k| k == kind))
+        };
+        let only = |list: &[String]| {
+            !kinds.is_empty()
+                && kinds
+                    .iter()
+                    .all(|kind| kind.is_some_and(|kind| list.iter().any(|k| k == kind)))
+        };
+        for (index, action) in self.actions.iter().enumerate() {
+            let triggered = match &action.condition {
+                Condition::Always => true,
+                Condition::Match(rule) => self.rules[*rule].matches(&context),
+                Condition::NotMatch(rule) => !self.rules[*rule].matches(&context),
+                Condition::AnyVariant(list) => any(list),
+                Condition::AllVariants(list) => all(list),
+                Condition::OnlyVariants(list) => only(list),
+            };
+            if triggered {
+                return Evaluation {
+                    disposition: action.disposition.clone(),
+                    reason: Reason::Action(index),
+                };
+            }
+        }
+
+        // The default actions of RFC 7940, section 7.6
+        let blocked = ["blocked".to_string()];
+        let allocatable = ["allocatable".to_string()];
+        let disposition = if any(&blocked) {
+            Disposition::Blocked
+        } else if all(&allocatable) {
+            Disposition::Allocatable
+        } else {
+            Disposition::Valid
+        };
+        Evaluation {
+            disposition,
+            reason: Reason::Default,
+        }
+    }
+}
+
+/// Compiles the classes and rules of a document.
+struct Builder<'a> {
+    rule_elements: HashMap<&'a str, &'a Element>,
+    class_elements: HashMap<&'a str, &'a Element>,
+    tags: HashMap<String, CodePointSet>,
+    classes: HashMap<&'a str, CodePointSet>,
+    rule_indices: HashMap<&'a str, usize>,
+    rules: Vec<Pattern>,
+    rule_names: Vec<String>,
+    /// Definitions being compiled, to detect cycles.
+    pending: HashSet<&'a str>,
+}
+
+const SET_OPERATORS: [&str; 6] = [
+    "class",
+    "union",
+    "complement",
+    "intersection",
+    "difference",
+    "symmetric-difference",
+];
+
+impl<'a> Builder<'a> {
+    fn new(data: &'a Element, rules: &'a Element) -> Result<Self, LgrError> {
+        let mut builder = Builder {
+            rule_elements: HashMap::new(),
+            class_elements: HashMap::new(),
+            tags: HashMap::new(),
+            classes: HashMap::new(),
+            rule_indices: HashMap::new(),
+            rules: Vec::new(),
+            rule_names: Vec::new(),
+            pending: HashSet::new(),
+        };
+        for element in &rules.children {
+            let Some(name) = element.attribute("name") else {
+                continue;
+            };
+            let definitions = if element.name == "rule" {
+                &mut builder.rule_elements
+            } else if SET_OPERATORS.contains(&element.name.as_str()) {
+                &mut builder.class_elements
+            } else {
+                continue;
+            };
+            if definitions.insert(name, element).is_some() {
+                return Err(LgrError::Invalid(format!("{name:?} is defined twice")));
+            }
+        }
+
+        let mut tagged: HashMap<String, Vec<(u32, u32)>> = HashMap::new();
+        for element in &data.children {
+            let Some(tags) = element.attribute("tag") else {
+                continue;
+            };
+            let range = match element.name.as_str() {
+                "char" => match code_points(element, "cp")?[..] {
+                    [cp] => (cp, cp),
+                    _ => return Err(invalid(element, "tags apply to single code points")),
+                },
+                "range" => range(element)?,
+                _ => continue,
+            };
+            for tag in tags.split_whitespace() {
+                tagged.entry(tag.to_string()).or_default().push(range);
+            }
+        }
+        builder.tags = tagged
+            .into_iter()
+            .map(|(tag, ranges)| (tag, CodePointSet::from_ranges(ranges)))
+            .collect();
+        Ok(builder)
+    }
+
+    fn build(mut self, data: &'a Element, rules: &'a Element) -> Result<Lgr, LgrError> {
+        let mut sequences = HashMap::new();
+        let mut ranges = Vec::new();
+        for element in &data.children {
+            match element.name.as_str() {
+                "char" => {
+                    let mut entry = self.entry(element)?;
+                    for variant in element.children.iter().filter(|e| e.name == "var") {
+                        entry.variants.push(Variant {
+                            code_points: code_points(variant, "cp")?,
+                            kind: variant.attribute("type").unwrap_or_default().to_string(),
+                            when: self.rule_reference(variant, "when")?,
+                            not_when: self.rule_reference(variant, "not-when")?,
+                        });
+                    }
+                    if sequences
+                        .insert(code_points(element, "cp")?, entry)
+                        .is_some()
+                    {
+                        return Err(invalid(element, "duplicate code point"));
+                    }
+                }
+                "range" => {
+                    let (first, last) = range(element)?;
+                    ranges.push((first, last, self.entry(element)?));
+                }
+                _ => {}
+            }
+        }
+        ranges.sort_by_key(|&(first, _, _)| first);
+        if ranges.windows(2).any(|pair| pair[0].1 >= pair[1].0)
+            || ranges.iter().any(|&(first, last, _)| {
+                sequences
+                    .keys()
+                    .any(|cps| cps.len() == 1 && (first..=last).contains(&cps[0]))
+            })
+        {
+            return Err(LgrError::Invalid("overlapping ranges".into()));
+        }
+
+        let mut actions = Vec::new();
+        for element in rules.children.iter().filter(|e| e.name == "action") {
+            let disposition = Disposition::parse(
+                element
+                    .attribute("disp")
+                    .ok_or_else(|| invalid(element, "action without disp"))?,
+            );
+            let list = |value: &str| value.split_whitespace().map(String::from).collect();
+            let condition = if let Some(rule) = element.attribute("match") {
+                Condition::Match(self.rule(rule)?)
+            } else if let Some(rule) = element.attribute("not-match") {
+                Condition::NotMatch(self.rule(rule)?)
+            } else if let Some(kinds) = element.attribute("any-variant") {
+                Condition::AnyVariant(list(kinds))
+            } else if let Some(kinds) = element.attribute("all-variants") {
+                Condition::AllVariants(list(kinds))
+            } else if let Some(kinds) = element.attribute("only-variants") {
+                Condition::OnlyVariants(list(kinds))
+            } else {
+                Condition::Always
+            };
+            actions.push(Action {
+                disposition,
+                condition,
+            });
+        }
+
+        Ok(Lgr {
+            longest_sequence: sequences.keys().map(Vec::len).max().unwrap_or(0),
+            sequences,
+            ranges,
+            rules: self.rules,
+            rule_names: self.rule_names,
+            actions,
+        })
+    }
+
+    fn entry(&mut self, element: &'a Element) -> Result<Entry, LgrError> {
+        Ok(Entry {
+            when: self.rule_reference(element, "when")?,
+            not_when: self.rule_reference(element, "not-when")?,
+            variants: Vec::new(),
+        })
+    }
+
+    fn rule_reference(
+        &mut self,
+        element: &'a Element,
+        attribute: &str,
+    ) -> Result<Option<usize>, LgrError> {
+        element
+            .attribute(attribute)
+            .map(|name| self.rule(name))
+            .transpose()
+    }
+
+    /// The index of the named rule, compiling it on first use.
+    fn rule(&mut self, name: &str) -> Result<usize, LgrError> {
+        if let Some(&index) = self.rule_indices.get(name) {
+            return Ok(index);
+        }
+        let (&name, &element) = self
+            .rule_elements
+            .get_key_value(name)
+            .ok_or_else(|| LgrError::Invalid(format!("undefined rule {name:?}")))?;
+        if !self.pending.insert(name) {
+            return Err(LgrError::Invalid(format!("rule {name:?} refers to itself")));
+        }
+        let pattern = self.pattern(&element.children)?;
+        self.pending.remove(name);
+        self.rules.push(pattern);
+        self.rule_names.push(name.to_string());
+        self.rule_indices.insert(name, self.rules.len() - 1);
+        Ok(self.rules.len() - 1)
+    }
+
+    fn pattern(&mut self, elements: &'a [Element]) -> Result<Pattern, LgrError> {
+        let terms = elements
+            .iter()
+            .map(|element| self.term(element))
+            .collect::<Result<_, _>>()?;
+        Ok(Pattern(terms))
+    }
+
+    fn term(&mut self, element: &'a Element) -> Result<Term, LgrError> {
+        let matcher = match element.name.as_str() {
+            "char" => Matcher::Sequence(code_points(element, "cp")?),
+            "any" => Matcher::Any,
+            "start" => Matcher::Start,
+            "end" => Matcher::End,
+            "anchor" => Matcher::Anchor,
+            "look-ahead" => Matcher::LookAhead(self.pattern(&element.children)?),
+            "look-behind" => Matcher::LookBehind(self.pattern(&element.children)?),
+            "choice" => Matcher::Choice(
+                element
+                    .children
+                    .iter()
+                    .map(|child| Ok(Pattern(vec![self.term(child)?])))
+                    .collect::<Result<_, _>>()?,
+            ),
+            "rule" => match element.attribute("by-ref") {
+                Some(name) => {
+                    let index = self.rule(name)?;
+                    Matcher::Group(self.rules[index].clone())
+                }
+                None => Matcher::Group(self.pattern(&element.children)?),
+            },
+            name if SET_OPERATORS.contains(&name) => Matcher::Class(self.class(element)?),
+            _ => return Err(invalid(element, "unknown match operator")),
+        };
+        let (min, max) = match element.attribute("count") {
+            None => (1, 1),
+            Some(count) => parse_count(count).ok_or_else(|| invalid(element, "invalid count"))?,
+        };
+        Ok(Term { matcher, min, max })
+    }
+
+    /// The code points of a class or set operator element.
+    fn class(&mut self, element: &'a Element) -> Result<CodePointSet, LgrError> {
+        if let Some(name) = element.attribute("by-ref") {
+            return self.named_class(name);
+        }
+        let operands = || {
+            element
+                .children
+                .iter()
+                .filter(|e| SET_OPERATORS.contains(&e.name.as_str()))
+        };
+        let mut sets = Vec::new();
+        for operand in operands() {
+            sets.push(self.class(operand)?);
+        }
+        let binary = |sets: &[CodePointSet]| match sets {
+            [a, b] => Ok((a.clone(), b.clone())),
+            _ => Err(invalid(element, "expected two operands")),
+        };
+        match element.name.as_str() {
+            "union" => Ok(sets
+                .iter()
+                .fold(CodePointSet::default(), |acc, set| acc.union(set))),
+            "complement" => match &sets[..] {
+                [set] => Ok(set.complement()),
+                _ => Err(invalid(element, "expected one operand")),
+            },
+            "intersection" => binary(&sets).map(|(a, b)| a.intersection(&b)),
+            "difference" => binary(&sets).map(|(a, b)| a.difference(&b)),
+            "symmetric-difference" => {
+                binary(&sets).map(|(a, b)| a.difference(&b).union(&b.difference(&a)))
+            }
+            _ => {
+                if let Some(tag) = element.attribute("from-tag") {
+                    return Ok(self.tags.get(tag).cloned().unwrap_or_default());
+                }
+                if let Some(property) = element.attribute("property") {
+                    return property_class(property);
+                }
+                let mut ranges = Vec::new();
+                for item in element.text.split_whitespace() {
+                    let (first, last) = item.split_once('-').unwrap_or((item, item));
+                    match (parse_code_point(first), parse_code_point(last)) {
+                        (Some(first), Some(last)) if first <= last => ranges.push((first, last)),
+                        _ => return Err(invalid(element, "invalid code point in class")),
+                    }
+                }
+                Ok(CodePointSet::from_ranges(ranges))
+            }
+        }
+    }
+
+    fn named_class(&mut self, name: &str) -> Result<CodePointSet, LgrError> {
+        if let Some(set) = self.classes.get(name) {
+            return Ok(set.clone());
+        }
+        let (&name, &element) = self
+            .class_elements
+            .get_key_value(name)
+            .ok_or_else(|| LgrError::Invalid(format!("undefined class {name:?}")))?;
+        if !self.pending.insert(name) {
+            return Err(LgrError::Invalid(format!(
+                "class {name:?} refers to itself"
+            )));
+        }
+        let set = self.class(element)?;
+        self.pending.remove(name);
+        self.classes.insert(name, set.clone());
+        Ok(set)
+    }
+}
+
+fn invalid(element: &Element, message: &str) -> LgrError {
+    LgrError::Invalid(format!(
+        "{message} in <{}> at byte {}",
+        element.name, element.position
+    ))
+}
+
+fn parse_code_point(hex: &str) -> Option<u32> {
+    let cp = u32::from_str_radix(hex, 16).ok()?;
+    char::from_u32(cp).map(u32::from)
+}
+
+/// The space-separated code points of an attribute.
+fn code_points(element: &Element, attribute: &str) -> Result<Vec<u32>, LgrError> {
+    let value = element
+        .attribute(attribute)
+        .ok_or_else(|| invalid(element, &format!("missing {attribute}")))?;
+    let code_points: Option<Vec<u32>> = value.split_whitespace().map(parse_code_point).collect();
+    match code_points {
+        Some(code_points) if !code_points.is_empty() => Ok(code_points),
+        _ => Err(invalid(element, &format!("invalid {attribute}"))),
+    }
+}
+
+fn range(element: &Element) -> Result<(u32, u32), LgrError> {
+    let first = code_points(element, "first-cp")?;
+    let last = code_points(element, "last-cp")?;
+    match (&first[..], &last[..]) {
+        (&[first], &[last]) if first <= last => Ok((first, last)),
+        _ => Err(invalid(element, "invalid range")),
+    }
+}
+
+/// Parses `n`, `n+` or `n:m`.
+fn parse_count(count: &str) -> Option<(usize, usize)> {
+    if let Some(min) = count.strip_suffix('+') {
+        return Some((min.parse().ok()?, usize::MAX));
+    }
+    if let Some((min, max)) = count.split_once(':') {
+        let (min, max) = (min.parse().ok()?, max.parse().ok()?);
+        return (min <= max).then_some((min, max));
+    }
+    let n = count.parse().ok()?;
+    Some((n, n))
+}
+
+fn property_class(property: &str) -> Result<CodePointSet, LgrError> {
+    let unsupported = || LgrError::Unsupported(format!("property {property:?}"));
+    let (name, value) = property.split_once(':').ok_or_else(unsupported)?;
+    if name != "ccc" {
+        return Err(unsupported());
+    }
+    let class: u8 = value.parse().map_err(|_| unsupported())?;
+    let mut ranges: Vec<(u32, u32)> = Vec::new();
+    for cp in (0..=0x10FFFF).filter(|&cp| get_ccc(cp) == class) {
+        match ranges.last_mut() {
+            Some((_, last)) if *last + 1 == cp => *last = cp,
+            _ => ranges.push((cp, cp)),
+        }
+    }
+    Ok(CodePointSet::from_ranges(ranges))
+}
+
+/// A set of code points as sorted, disjoint, non-adjacent ranges.
+#[derive(Debug, Clone, Default)]
+struct CodePointSet(Vec<(u32, u32)>);
+
+impl CodePointSet {
+    fn from_ranges(mut ranges: Vec<(u32, u32)>) -> Self {
+        ranges.sort_unstable();
+        let mut merged: Vec<(u32, u32)> = Vec::with_capacity(ranges.len());
+        for (first, last) in ranges {
+            match merged.last_mut() {
+                Some((_, end)) if first <= end.saturating_add(1) => *end = (*end).max(last),
+                _ => merged.push((first, last)),
+            }
+        }
+        CodePointSet(merged)
+    }
+
+    fn contains(&self, cp: u32) -> bool {
+        let i = self.0.partition_point(|&(_, last)| last < cp);
+        self.0.get(i).is_some_and(|&(first, _)| first <= cp)
+    }
+
+    fn union(&self, other: &CodePointSet) -> CodePointSet {
+        CodePointSet::from_ranges(self.0.iter().chain(&other.0).copied().collect())
+    }
+
+    fn complement(&self) -> CodePointSet {
+        let mut ranges = Vec::with_capacity(self.0.len() + 1);
+        let mut next = 0;
+        for &(first, last) in &self.0 {
+            if first > next {
+                ranges.push((next, first - 1));
+            }
+            next = last + 1;
+        }
+        if next <= 0x10FFFF {
+            ranges.push((next, 0x10FFFF));
+        }
+        CodePointSet(ranges)
+    }
+
+    fn intersection(&self, other: &CodePointSet) -> CodePointSet {
+        self.complement().union(&other.complement()).complement()
+    }
+
+    fn difference(&self, other: &CodePointSet) -> CodePointSet {
+        self.intersection(&other.complement())
+    }
+}
+
+/// A compiled rule: a sequence of match operators.
+#[derive(Debug, Clone)]
+struct Pattern(Vec<Term>);
+
+#[derive(Debug, Clone)]
+struct Term {
+    matcher: Matcher,
+    min: usize,
+    max: usize,
+}
+
+#[derive(Debug, Clone)]
+enum Matcher {
+    Sequence(Vec<u32>),
+    Class(CodePointSet),
+    Any,
+    Start,
+    End,
+    Anchor,
+    LookAhead(Pattern),
+    LookBehind(Pattern),
+    Choice(Vec<Pattern>),
+    Group(Pattern),
+}
+
+/// The label a rule is matched against, and for context rules the position
+/// and length of the code point or sequence that `<anchor/>` stands for.
+struct Context<'a> {
+    label: &'a [u32],
+    anchor: Option<(usize, usize)>,
+}
+
+impl Pattern {
+    /// Returns `true` if the rule matches anywhere in the label. Rules are
+    /// tied to the start or end of the label by `<start/>` and `<end/>`.
+    fn matches(&self, context: &Context<'_>) -> bool {
+        (0..=context.label.len()).any(|start| self.match_at(context, start, &mut |_| true))
+    }
+
+    /// Matches the rule from `pos`, calling `next` with every possible end
+    /// until it returns `true`.
+    fn match_at(
+        &self,
+        context: &Context<'_>,
+        pos: usize,
+        next: &mut dyn FnMut(usize) -> bool,
+    ) -> bool {
+        match_terms(&self.0, context, pos, next)
+    }
+}
+
+fn match_terms(
+    terms: &[Term],
+    context: &Context<'_>,
+    pos: usize,
+    next: &mut dyn FnMut(usize) -> bool,
+) -> bool {
+    match terms.split_first() {
+        None => next(pos),
+        Some((term, rest)) => match_repeated(term, 0, context, pos, &mut |end| {
+            match_terms(rest, context, end, next)
+        }),
+    }
+}
+
+/// Matches further repetitions of `term` greedily, backtracking to fewer.
+fn match_repeated(
+    term: &Term,
+    count: usize,
+    context: &Context<'_>,
+    pos: usize,
+    next: &mut dyn FnMut(usize) -> bool,
+) -> bool {
+    if count < term.max
+        && match_once(&term.matcher, context, pos, &mut |end| {
+            // Stop repeating empty matches once the minimum is reached
+            (end > pos || count < term.min) && match_repeated(term, count + 1, context, end, next)
+        })
+    {
+        return true;
+    }
+    count >= term.min && next(pos)
+}
+
+fn match_once(
+    matcher: &Matcher,
+    context: &Context<'_>,
+    pos: usize,
+    next: &mut dyn FnMut(usize) -> bool,
+) -> bool {
+    let label = context.label;
+    match matcher {
+        Matcher::Sequence(code_points) => {
+            label[pos..].starts_with(code_points) && next(pos + code_points.len())
+        }
+        Matcher::Class(set) => pos < label.len() && set.contains(label[pos]) && next(pos + 1),
+        Matcher::Any => pos < label.len() && next(pos + 1),
+        Matcher::Start => pos == 0 && next(pos),
+        Matcher::End => pos == label.len() && next(pos),
+        Matcher::Anchor => match context.anchor {
+            Some((start, len)) if start == pos => next(pos + len),
+            _ => false,
+        },
+        Matcher::LookAhead(pattern) => pattern.match_at(context, pos, &mut |_| true) && next(pos),
+        Matcher::LookBehind(pattern) => {
+            (0..=pos).any(|start| pattern.match_at(context, start, &mut |end| end == pos))
+                && next(pos)
+        }
+        Matcher::Choice(alternatives) => alternatives
+            .iter()
+            .any(|alternative| alternative.match_at(context, pos, next)),
+        Matcher::Group(pattern) => pattern.match_at(context, pos, next),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lgr(rules: &str) -> Lgr {
+        Lgr::parse(&format!(
+            "<lgr><data><range first-cp=\"0061\" last-cp=\"007A\"/>\
+             <char cp=\"002D\"/><char cp=\"0301\"/>\
+             <char cp=\"00B7\" when=\"catalan\"/></data>\
+             <rules>{rules}</rules></lgr>"
+        ))
+        .unwrap()
+    }
+
+    const CATALAN: &str = "<rule name=\"catalan\"><look-behind><char cp=\"006C\"/></look-behind>\
+                           <anchor/><look-ahead><char cp=\"006C\"/></look-ahead></rule>";
+
+    #[test]
+    fn test_repertoire_and_context() {
+        let lgr = lgr(CATALAN);
+        assert_eq!(
+            lgr.evaluate("col\u{b7}lecta").disposition,
+            Disposition::Valid
+        );
+        assert_eq!(
+            lgr.evaluate("co\u{b7}lecta").reason,
+            Reason::ContextRule {
+                position: 2,
+                rule: "catalan".into()
+            }
+        );
+        assert_eq!(
+            lgr.evaluate("caf\u{e9}").reason,
+            Reason::NotInRepertoire {
+                position: 3,
+                code_point: '\u{e9}'
+            }
+        );
+    }
+
+    #[test]
+    fn test_rules() {
+        let lgr = lgr(&format!(
+            "{CATALAN}\
+             <class name=\"vowel\">0061 0065 0069 006F 0075</class>\
+             <difference name=\"consonant\"><class>0061-007A</class><class by-ref=\"vowel\"/></difference>\
+             <rule name=\"leading-mark\"><start/><class property=\"ccc:230\"/></rule>\
+             <rule name=\"hyphens\"><any count=\"2\"/><char cp=\"002D\" count=\"2\"/></rule>\
+             <rule name=\"consonants\"><class by-ref=\"consonant\" count=\"4+\"/></rule>\
+             <rule name=\"vowel-end\"><choice><class by-ref=\"vowel\"/><char cp=\"0079\"/></choice><end/></rule>\
+             <action disp=\"invalid\" match=\"leading-mark\"/>\
+             <action disp=\"invalid\" match=\"hyphens\"/>\
+             <action disp=\"blocked\" match=\"consonants\"/>\
+             <action disp=\"reserved\" not-match=\"vowel-end\"/>"
+        ));
+        let evaluate = |label| {
+            let evaluation = lgr.evaluate(label);
+            (evaluation.disposition, evaluation.reason)
+        };
+        assert_eq!(
+            evaluate("\u{301}a"),
+            (Disposition::Invalid, Reason::Action(0))
+        );
+        assert_eq!(evaluate("ab--c"), (Disposition::Invalid, Reason::Action(1)));
+        assert_eq!(
+            evaluate("a-b-c"),
+            (Disposition::Other("reserved".into()), Reason::Action(3))
+        );
+        assert_eq!(
+            evaluate("strength"),
+            (Disposition::Blocked, Reason::Action(2))
+        );
+        assert_eq!(evaluate("tasty"), (Disposition::Valid, Reason::Default));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let parse = |rules: &str| {
+            Lgr::parse(&format!(
+                "<lgr><data><char cp=\"0061\"/></data><rules>{rules}</rules></lgr>"
+            ))
+        };
+        assert!(matches!(Lgr::parse("<lgr>"), Err(LgrError::Xml(5))));
+        assert!(matches!(Lgr::parse("<lgr/>"), Err(LgrError::Invalid(_))));
+        assert!(matches!(
+            parse("<action disp=\"invalid\" match=\"x\"/>"),
+            Err(LgrError::Invalid(_))
+        ));
+        assert!(matches!(
+            parse(
+                "<rule name=\"x\"><rule by-ref=\"x\"/></rule><action disp=\"invalid\" match=\"x\"/>"
+            ),
+            Err(LgrError::Invalid(_))
+        ));
+        assert!(matches!(
+            parse(
+                "<rule name=\"x\"><class property=\"gc:Mn\"/></rule><action disp=\"invalid\" match=\"x\"/>"
+            ),
+            Err(LgrError::Unsupported(_))
+        ));
+        assert!(matches!(
+            parse(
+                "<rule name=\"x\"><any count=\"3:1\"/></rule><action disp=\"invalid\" match=\"x\"/>"
+            ),
+            Err(LgrError::Invalid(_))
+        ));
+    }
+}
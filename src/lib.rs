@@ -1,16 +1,50 @@
+#![cfg_attr(not(feature = "simd"), forbid(unsafe_code))]
+#![cfg_attr(feature = "simd", deny(unsafe_code))]
+
+mod ascii;
+pub mod display;
 pub mod domain;
+pub mod host;
+#[cfg(feature = "idna-compat")]
+pub mod idna_compat;
+pub mod lgr;
 pub mod mapping;
 pub mod normalization;
 pub mod punycode;
+pub mod script;
+pub mod security;
 pub mod unicode;
 pub mod unicode_tables;
+pub mod uts46;
 pub mod validation;
+mod xml;
 
-pub use domain::{IdnaError, to_ascii, to_unicode};
-pub use mapping::{ascii_map, map};
-pub use normalization::normalize;
-pub use punycode::{punycode_to_utf32, utf32_to_punycode, verify_punycode};
-pub use unicode::{utf8_length_from_utf32, utf8_to_utf32, utf32_length_from_utf8, utf32_to_utf8};
+pub use display::{DisplayDomain, DisplayPolicy, FallbackReason, to_display_unicode};
+pub use domain::{
+    IdnaError, to_ascii, to_ascii_utf16, to_ascii_with_options, to_unicode, to_unicode_utf16,
+    to_unicode_with_options,
+};
+pub use host::{Host, HostError, parse_host, url_domain_to_ascii, url_domain_to_unicode};
+pub use lgr::{Lgr, LgrError, VariantLabel};
+pub use mapping::{ascii_map, idna_equivalent, map, map_chars};
+pub use normalization::{canonically_equivalent, normalize, normalize_chars};
+pub use punycode::{
+    Bootstring, PUNYCODE, PunycodeError, PunycodeErrorKind, decode_label, punycode_encoded_len,
+    punycode_encoded_len_str, punycode_to_utf32, punycode_to_utf32_with_case,
+    punycode_to_utf32_with_limit, utf32_to_punycode, utf32_to_punycode_with_case, verify_punycode,
+};
+pub use script::{Script, ScriptSet, script_extensions};
+pub use security::{
+    Finding, FindingKind, RestrictionLevel, are_confusable, audit, restriction_level, skeleton,
+};
+pub use unicode::{
+    EncodeUtf8, Utf8CodePoints, Utf8Error, Utf16Error, Utf32Error, utf8_length_from_utf32,
+    utf8_length_from_utf32_checked, utf8_to_utf32, utf8_to_utf32_lossy, utf16_to_utf32,
+    utf16_to_utf32_lossy, utf32_length_from_utf8, utf32_length_from_utf8_checked, utf32_to_utf8,
+    utf32_to_utf16,
+};
+pub use uts46::{Uts46Error, Uts46Options};
 pub use validation::{
     contains_forbidden_domain_code_point, is_ascii, is_label_valid, valid_name_code_point,
+    whole_script_confusables,
 };
@@ -1,3 +1,5 @@
+use crate::normalization::{CanonicalDecomposition, Normalize, normalize_chars};
+
 pub fn ascii_map(input: &str) -> String {
     input
         .chars()
@@ -15,57 +17,118 @@ pub fn map(input: &str) -> String {
     // Apply normalization first, as per C++ implementation
     let normalized = crate::normalization::normalize(input);
     let mut result = String::with_capacity(normalized.len());
+    result.extend(Mapping::new(normalized.chars()));
+    result
+}
+
+/// Returns `true` if `a` and `b` are equivalent for IDNA lookup purposes:
+/// after mapping (case folding, removal of ignored characters, ...) both are
+/// canonically equivalent.
+///
+/// Unlike comparing the results of [`map`], neither input is materialized;
+/// both are streamed through decomposition and mapping and compared code point
+/// by code point.
+pub fn idna_equivalent(a: &str, b: &str) -> bool {
+    fn mapped(input: &str) -> impl Iterator<Item = u32> + '_ {
+        let decomposed = CanonicalDecomposition::new(input.chars()).filter_map(char::from_u32);
+        CanonicalDecomposition::new(Mapping::new(decomposed))
+    }
+    mapped(a).eq(mapped(b))
+}
+
+/// Streams the result of [`map`] for a character sequence.
+pub fn map_chars<I: IntoIterator<Item = char>>(input: I) -> Mapping<Normalize<I::IntoIter>> {
+    Mapping::new(normalize_chars(input))
+}
+
+/// Returns `true` for the code points [`map`] removes.
+pub(crate) fn is_ignored(c: char) -> bool {
+    matches!(c, '\u{00AD}' | '\u{200C}'..='\u{200F}' | '\u{202A}'..='\u{202E}')
+}
+
+/// Applies the per-character mappings of [`map`] to a character sequence.
+///
+/// Returned by [`map_chars`].
+pub struct Mapping<I> {
+    iter: I,
+    pending: [char; 3],
+    pending_len: u8,
+    pending_pos: u8,
+}
+
+impl<I: Iterator<Item = char>> Mapping<I> {
+    pub(crate) fn new(iter: I) -> Self {
+        Self {
+            iter,
+            pending: ['\0'; 3],
+            pending_len: 0,
+            pending_pos: 0,
+        }
+    }
+
+    fn set_pending(&mut self, chars: &[char]) -> Option<char> {
+        self.pending[..chars.len() - 1].copy_from_slice(&chars[1..]);
+        self.pending_len = (chars.len() - 1) as u8;
+        self.pending_pos = 0;
+        Some(chars[0])
+    }
+}
+
+impl<I: Iterator<Item = char>> Iterator for Mapping<I> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        if self.pending_pos < self.pending_len {
+            let c = self.pending[self.pending_pos as usize];
+            self.pending_pos += 1;
+            return Some(c);
+        }
+
+        loop {
+            let c = self.iter.next()?;
+            return match c {
+                // Soft hyphen, zero-width joiners and bidi formatting - remove
+                _ if is_ignored(c) => continue,
 
-    for c in normalized.chars() {
-        match c {
-            // Soft hyphen - remove completely
-            '\u{00AD}' => continue,
-
-            // Space normalization - convert various space characters to regular space
-            '\u{00A0}' | '\u{2002}' | '\u{2003}' => result.push(' '), // Non-breaking space, en space, em space -> regular space
-
-            // Special case mappings
-            '\u{00DF}' => result.push_str("ss"),
-            '\u{0130}' => result.push_str("i\u{0307}"),
-            '\u{FB00}' => result.push_str("ff"),
-            '\u{FB01}' => result.push_str("fi"),
-            '\u{FB02}' => result.push_str("fl"),
-            '\u{FB03}' => result.push_str("ffi"),
-            '\u{FB04}' => result.push_str("ffl"),
-            '\u{FB05}' => result.push_str("st"),
-            '\u{FB06}' => result.push_str("st"),
-            '\u{0587}' => result.push_str("\u{0565}\u{0582}"),
-            '\u{FB13}' => result.push_str("\u{0574}\u{0576}"),
-            '\u{FB14}' => result.push_str("\u{0574}\u{0565}"),
-            '\u{FB15}' => result.push_str("\u{0574}\u{056B}"),
-            '\u{FB16}' => result.push_str("\u{057E}\u{0576}"),
-            '\u{FB17}' => result.push_str("\u{0574}\u{056D}"),
-
-            // Zero-width characters - remove
-            '\u{200C}' | '\u{200D}' => continue,
-
-            // Format characters - remove
-            '\u{200E}' | '\u{200F}' => continue,
-            '\u{202A}' | '\u{202B}' | '\u{202C}' | '\u{202D}' | '\u{202E}' => continue,
-
-            // Case folding - optimize for common ASCII case
-            _ => {
-                // Fast path for ASCII characters
-                if c.is_ascii() && c.is_ascii_uppercase() {
-                    result.push(c.to_ascii_lowercase());
-                } else if c.is_ascii() {
-                    result.push(c);
-                } else {
-                    // Full Unicode case folding for non-ASCII
-                    for lowercase_char in c.to_lowercase() {
-                        result.push(lowercase_char);
+                // Space normalization - convert various space characters to regular space
+                '\u{00A0}' | '\u{2002}' | '\u{2003}' => Some(' '), // Non-breaking space, en space, em space -> regular space
+
+                // Special case mappings
+                '\u{00DF}' => self.set_pending(&['s', 's']),
+                '\u{0130}' => self.set_pending(&['i', '\u{0307}']),
+                '\u{FB00}' => self.set_pending(&['f', 'f']),
+                '\u{FB01}' => self.set_pending(&['f', 'i']),
+                '\u{FB02}' => self.set_pending(&['f', 'l']),
+                '\u{FB03}' => self.set_pending(&['f', 'f', 'i']),
+                '\u{FB04}' => self.set_pending(&['f', 'f', 'l']),
+                '\u{FB05}' => self.set_pending(&['s', 't']),
+                '\u{FB06}' => self.set_pending(&['s', 't']),
+                '\u{0587}' => self.set_pending(&['\u{0565}', '\u{0582}']),
+                '\u{FB13}' => self.set_pending(&['\u{0574}', '\u{0576}']),
+                '\u{FB14}' => self.set_pending(&['\u{0574}', '\u{0565}']),
+                '\u{FB15}' => self.set_pending(&['\u{0574}', '\u{056B}']),
+                '\u{FB16}' => self.set_pending(&['\u{057E}', '\u{0576}']),
+                '\u{FB17}' => self.set_pending(&['\u{0574}', '\u{056D}']),
+
+                // Case folding - optimize for common ASCII case
+                _ => {
+                    if c.is_ascii() {
+                        // Fast path for ASCII characters
+                        Some(c.to_ascii_lowercase())
+                    } else {
+                        // Full Unicode case folding for non-ASCII
+                        let mut lower = ['\0'; 3];
+                        let mut len = 0;
+                        for lowercase_char in c.to_lowercase() {
+                            lower[len] = lowercase_char;
+                            len += 1;
+                        }
+                        self.set_pending(&lower[..len])
                     }
                 }
-            }
+            };
         }
     }
-
-    result
 }
 
 #[cfg(test)]
@@ -83,5 +146,31 @@ mod tests {
     fn test_map_with_special_chars() {
         assert_eq!(map("ß"), "ss");
         assert_eq!(map("İ"), "i̇");
+        assert_eq!(map("a\u{00AD}b\u{200C}\u{200D}c\u{200E}\u{202E}d"), "abcd");
+        assert_eq!(map("a\u{200B}\u{2066}b"), "a\u{200B}\u{2066}b");
+    }
+
+    #[test]
+    fn test_idna_equivalent() {
+        assert!(idna_equivalent("Example", "eXample"));
+        assert!(idna_equivalent("straße", "STRASSE"));
+        assert!(idna_equivalent("soft\u{00AD}hyphen", "softhyphen"));
+        assert!(idna_equivalent("\u{D55C}", "\u{1112}\u{1161}\u{11AB}"));
+        assert!(!idna_equivalent("example", "examples"));
+        assert!(!idna_equivalent("a", "b"));
+    }
+
+    #[test]
+    fn test_map_chars() {
+        for input in [
+            "Example",
+            "STRASSE",
+            "straße",
+            "soft\u{00AD}hyphen",
+            "ΣΑΣ",
+            "e\u{0302}\u{0323}",
+        ] {
+            assert_eq!(map_chars(input.chars()).collect::<String>(), map(input));
+        }
     }
 }
@@ -1,118 +1,281 @@
 use crate::unicode_tables::*;
 
 pub fn normalize(input: &str) -> String {
-    // Optimize: Pre-allocate with estimated capacity
-    let char_count = input.chars().count();
-    let mut chars: Vec<u32> = Vec::with_capacity(char_count + char_count / 4); // Allow for expansion
-    chars.extend(input.chars().map(|c| c as u32));
+    normalize_chars(input.chars()).collect()
+}
+
+/// Appends the full canonical decomposition of `c` to `out`, or `c` itself
+/// when it has none.
+fn push_canonical_decomposition(c: u32, out: &mut impl Extend<u32>) {
+    if (HANGUL_SBASE..HANGUL_SBASE + HANGUL_SCOUNT).contains(&c) {
+        let s_index = c - HANGUL_SBASE;
+        out.extend([
+            HANGUL_LBASE + s_index / HANGUL_NCOUNT,
+            HANGUL_VBASE + (s_index % HANGUL_NCOUNT) / HANGUL_TCOUNT,
+        ]);
+        if !s_index.is_multiple_of(HANGUL_TCOUNT) {
+            out.extend([HANGUL_TBASE + s_index % HANGUL_TCOUNT]);
+        }
+        return;
+    }
+
+    if c < 0x110000 {
+        let di = DECOMPOSITION_INDEX[(c >> 8) as usize];
+        let decomposition = &DECOMPOSITION_BLOCK[di as usize];
+        let idx = (c % 256) as usize;
+        if idx < decomposition.len() - 1 && (decomposition[idx] & 1) == 0 {
+            let start = (decomposition[idx] >> 2) as usize;
+            let end = ((decomposition[idx + 1] >> 2) as usize).min(DECOMPOSITION_DATA.len());
+            if start < end {
+                out.extend(DECOMPOSITION_DATA[start..end].iter().copied());
+                return;
+            }
+        }
+    }
 
-    // Decompose and reorder (NFC normalization)
-    decompose_nfc(&mut chars);
-    compose(&mut chars);
+    out.extend([c]);
+}
 
-    // Convert back to string
-    chars.into_iter().filter_map(char::from_u32).collect()
+/// Streams the canonically ordered, canonically decomposed (NFD) code points
+/// of a character sequence.
+///
+/// Only the current run of combining marks is buffered, so two inputs can be
+/// compared code point by code point without normalizing them up front.
+pub(crate) struct CanonicalDecomposition<I> {
+    iter: I,
+    buffer: SmallBuffer<u32>,
+    ready: usize,
+    pos: usize,
 }
 
-fn decompose_nfc(input: &mut Vec<u32>) {
-    let (decomposition_needed, additional_elements) = compute_decomposition_length(input);
-    if decomposition_needed {
-        decompose(input, additional_elements);
+impl<I: Iterator<Item = char>> CanonicalDecomposition<I> {
+    pub(crate) fn new(iter: I) -> Self {
+        Self {
+            iter,
+            buffer: SmallBuffer::new(),
+            ready: 0,
+            pos: 0,
+        }
     }
-    sort_marks(input);
 }
 
-fn compute_decomposition_length(input: &[u32]) -> (bool, usize) {
-    let mut decomposition_needed = false;
-    let mut additional_elements = 0;
+impl<I: Iterator<Item = char>> Iterator for CanonicalDecomposition<I> {
+    type Item = u32;
 
-    for &current_character in input {
-        let mut decomposition_length = 0;
-
-        if (HANGUL_SBASE..HANGUL_SBASE + HANGUL_SCOUNT).contains(&current_character) {
-            decomposition_length = 2;
-            if (current_character - HANGUL_SBASE) % HANGUL_TCOUNT != 0 {
-                decomposition_length = 3;
+    fn next(&mut self) -> Option<u32> {
+        loop {
+            if self.pos < self.ready {
+                let c = self.buffer.as_slice()[self.pos];
+                self.pos += 1;
+                return Some(c);
             }
-        } else if current_character < 0x110000 {
-            let di = DECOMPOSITION_INDEX[(current_character >> 8) as usize];
-            let decomposition = &DECOMPOSITION_BLOCK[di as usize];
-            let idx = (current_character % 256) as usize;
-            if idx < decomposition.len() - 1 {
-                decomposition_length =
-                    ((decomposition[idx + 1] >> 2) - (decomposition[idx] >> 2)) as usize;
-                if decomposition_length > 0 && (decomposition[idx] & 1) != 0 {
-                    decomposition_length = 0;
+
+            self.buffer.remove_front(self.pos);
+            self.pos = 0;
+            self.ready = 0;
+
+            match self.iter.next() {
+                Some(c) => {
+                    let start = self.buffer.len();
+                    push_canonical_decomposition(c as u32, &mut self.buffer);
+                    // Everything before the last starter can no longer be
+                    // reordered by marks that follow it.
+                    if let Some(last_starter) = (start.max(1)..self.buffer.len())
+                        .rev()
+                        .find(|&i| get_ccc(self.buffer.as_slice()[i]) == 0)
+                    {
+                        sort_marks(&mut self.buffer.as_mut_slice()[..last_starter]);
+                        self.ready = last_starter;
+                    }
+                }
+                None => {
+                    if self.buffer.is_empty() {
+                        return None;
+                    }
+                    sort_marks(self.buffer.as_mut_slice());
+                    self.ready = self.buffer.len();
                 }
             }
         }
-
-        if decomposition_length != 0 {
-            decomposition_needed = true;
-            additional_elements += decomposition_length - 1;
-        }
     }
+}
 
-    (decomposition_needed, additional_elements)
+/// Streams the NFC form of a character sequence; the iterator counterpart of
+/// [`normalize`].
+///
+/// Like the decomposition it is built on, only the marks following the
+/// current starter are buffered, and nothing is allocated unless a run of
+/// marks is unusually long.
+pub fn normalize_chars<I: IntoIterator<Item = char>>(input: I) -> Normalize<I::IntoIter> {
+    Normalize {
+        decomposed: CanonicalDecomposition::new(input.into_iter()),
+        segment: SmallBuffer::new(),
+        last_ccc: None,
+        ready: 0,
+        pos: 0,
+        next_starter: None,
+        finished: false,
+    }
 }
 
-fn decompose(input: &mut Vec<u32>, additional_elements: usize) {
-    input.resize(input.len() + additional_elements, 0);
-    let input_count = input.len() - additional_elements;
-    let mut descending_idx = input.len();
+/// Iterator returned by [`normalize_chars`].
+pub struct Normalize<I> {
+    decomposed: CanonicalDecomposition<I>,
+    /// The current starter followed by the marks that did not combine with it.
+    segment: SmallBuffer<char>,
+    /// Combining class of the last mark in `segment`.
+    last_ccc: Option<u8>,
+    /// Length of the prefix of `segment` that is final, and how much of it
+    /// has been returned.
+    ready: usize,
+    pos: usize,
+    /// The starter that ended `segment`.
+    next_starter: Option<char>,
+    finished: bool,
+}
 
-    for i in (0..input_count).rev() {
-        let current_char = input[i];
+impl<I: Iterator<Item = char>> Iterator for Normalize<I> {
+    type Item = char;
 
-        if (HANGUL_SBASE..HANGUL_SBASE + HANGUL_SCOUNT).contains(&current_char) {
-            // Hangul decomposition
-            let s_index = current_char - HANGUL_SBASE;
-            if s_index % HANGUL_TCOUNT != 0 {
-                descending_idx -= 1;
-                input[descending_idx] = HANGUL_TBASE + s_index % HANGUL_TCOUNT;
+    fn next(&mut self) -> Option<char> {
+        loop {
+            if self.pos < self.ready {
+                let c = self.segment.as_slice()[self.pos];
+                self.pos += 1;
+                return Some(c);
             }
-            descending_idx -= 1;
-            input[descending_idx] = HANGUL_VBASE + (s_index % HANGUL_NCOUNT) / HANGUL_TCOUNT;
-            descending_idx -= 1;
-            input[descending_idx] = HANGUL_LBASE + s_index / HANGUL_NCOUNT;
-        } else if current_char < 0x110000 {
-            // Check decomposition data
-            let di = DECOMPOSITION_INDEX[(current_char >> 8) as usize];
-            let decomposition = &DECOMPOSITION_BLOCK[di as usize];
-            let idx = (current_char % 256) as usize;
-
-            let mut decomposition_length = 0;
-            if idx < decomposition.len() - 1 {
-                decomposition_length = (decomposition[idx + 1] >> 2) - (decomposition[idx] >> 2);
-                if decomposition_length > 0 && (decomposition[idx] & 1) != 0 {
-                    decomposition_length = 0;
-                }
+            if self.ready > 0 {
+                self.segment.clear();
+                self.ready = 0;
+                self.pos = 0;
+                self.last_ccc = None;
+                self.segment.extend(self.next_starter.take());
+            }
+            if self.finished {
+                return None;
             }
 
-            if decomposition_length > 0 {
-                // Non-recursive decomposition
-                let start_idx = (decomposition[idx] >> 2) as usize;
-                for j in 0..decomposition_length {
-                    if start_idx + (j as usize) < DECOMPOSITION_DATA.len() {
-                        descending_idx -= 1;
-                        input[descending_idx] =
-                            DECOMPOSITION_DATA[start_idx + (decomposition_length - 1 - j) as usize];
-                    }
+            let Some(c) = self.decomposed.by_ref().find_map(char::from_u32) else {
+                self.finished = true;
+                self.ready = self.segment.len();
+                continue;
+            };
+            let ccc = get_ccc(c as u32);
+            let Some(&starter) = self.segment.as_slice().first() else {
+                if ccc != 0 {
+                    // Nothing before it to combine with
+                    return Some(c);
                 }
+                self.segment.push(c);
+                continue;
+            };
+
+            // A mark is blocked from the starter by an uncombined mark of the
+            // same or higher class.
+            let blocked = self.last_ccc.is_some_and(|last| last >= ccc);
+            if !blocked && let Some(composite) = compose_pair(starter, c) {
+                self.segment.as_mut_slice()[0] = composite;
+                continue;
+            }
+            if ccc == 0 {
+                self.ready = self.segment.len();
+                self.next_starter = Some(c);
             } else {
-                // No decomposition
-                descending_idx -= 1;
-                input[descending_idx] = current_char;
+                self.segment.push(c);
+                self.last_ccc = Some(ccc);
+            }
+        }
+    }
+}
+
+/// Number of elements a [`SmallBuffer`] holds before moving to the heap.
+const INLINE_CAPACITY: usize = 16;
+
+/// A growable buffer that lives on the stack until it outgrows
+/// [`INLINE_CAPACITY`] elements.
+enum SmallBuffer<T> {
+    Inline([T; INLINE_CAPACITY], usize),
+    Heap(Vec<T>),
+}
+
+impl<T: Copy + Default> SmallBuffer<T> {
+    fn new() -> Self {
+        Self::Inline([T::default(); INLINE_CAPACITY], 0)
+    }
+
+    fn as_slice(&self) -> &[T] {
+        match self {
+            Self::Inline(items, len) => &items[..*len],
+            Self::Heap(items) => items,
+        }
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [T] {
+        match self {
+            Self::Inline(items, len) => &mut items[..*len],
+            Self::Heap(items) => items,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.as_slice().len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn push(&mut self, item: T) {
+        match self {
+            Self::Inline(items, len) if *len < INLINE_CAPACITY => {
+                items[*len] = item;
+                *len += 1;
+            }
+            Self::Inline(items, len) => {
+                let mut heap = Vec::with_capacity(2 * INLINE_CAPACITY);
+                heap.extend_from_slice(&items[..*len]);
+                heap.push(item);
+                *self = Self::Heap(heap);
             }
-        } else {
-            // Non-Unicode character
-            descending_idx -= 1;
-            input[descending_idx] = current_char;
+            Self::Heap(items) => items.push(item),
+        }
+    }
+
+    fn remove_front(&mut self, count: usize) {
+        match self {
+            Self::Inline(items, len) => {
+                items.copy_within(count..*len, 0);
+                *len -= count;
+            }
+            Self::Heap(items) => {
+                items.drain(..count);
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.remove_front(self.len());
+    }
+}
+
+impl<T: Copy + Default> Extend<T> for SmallBuffer<T> {
+    fn extend<It: IntoIterator<Item = T>>(&mut self, iter: It) {
+        for item in iter {
+            self.push(item);
         }
     }
 }
 
-fn get_ccc(c: u32) -> u8 {
+/// Returns `true` if `a` and `b` are canonically equivalent, i.e. they have
+/// the same NFD form.
+///
+/// Both inputs are decomposed incrementally and the comparison stops at the
+/// first difference.
+pub fn canonically_equivalent(a: &str, b: &str) -> bool {
+    CanonicalDecomposition::new(a.chars()).eq(CanonicalDecomposition::new(b.chars()))
+}
+
+pub(crate) fn get_ccc(c: u32) -> u8 {
     if c < 0x110000 {
         let idx = CANONICAL_COMBINING_CLASS_INDEX[(c >> 8) as usize] as usize;
         CANONICAL_COMBINING_CLASS_BLOCK[idx][(c % 256) as usize]
@@ -121,6 +284,51 @@ fn get_ccc(c: u32) -> u8 {
     }
 }
 
+/// Returns `true` if NFC never combines `c` with the characters before it, so
+/// the text from `c` on can be normalized separately.
+pub(crate) fn is_segment_start(c: char) -> bool {
+    let cp = c as u32;
+    if get_ccc(cp) != 0
+        || (HANGUL_VBASE..HANGUL_VBASE + HANGUL_VCOUNT).contains(&cp)
+        || (HANGUL_TBASE + 1..HANGUL_TBASE + HANGUL_TCOUNT).contains(&cp)
+    {
+        return false;
+    }
+    // A few starters, such as U+0DCF, are the second half of a composite
+    cp < 0x80 || COMPOSITION_SECONDS.binary_search(&cp).is_err()
+}
+
+/// The primary composite of `starter` and `c`, if there is one.
+fn compose_pair(starter: char, c: char) -> Option<char> {
+    let (starter, c) = (starter as u32, c as u32);
+    if (HANGUL_LBASE..HANGUL_LBASE + HANGUL_LCOUNT).contains(&starter)
+        && (HANGUL_VBASE..HANGUL_VBASE + HANGUL_VCOUNT).contains(&c)
+    {
+        let lv = HANGUL_SBASE
+            + ((starter - HANGUL_LBASE) * HANGUL_VCOUNT + c - HANGUL_VBASE) * HANGUL_TCOUNT;
+        return char::from_u32(lv);
+    }
+    if (HANGUL_SBASE..HANGUL_SBASE + HANGUL_SCOUNT).contains(&starter) {
+        if (starter - HANGUL_SBASE).is_multiple_of(HANGUL_TCOUNT)
+            && c > HANGUL_TBASE
+            && c < HANGUL_TBASE + HANGUL_TCOUNT
+        {
+            return char::from_u32(starter + c - HANGUL_TBASE);
+        }
+        return None;
+    }
+
+    let ci = COMPOSITION_INDEX[(starter >> 8) as usize] as usize;
+    let block = &COMPOSITION_BLOCK[ci];
+    let idx = (starter % 256) as usize;
+    let start = block[idx] as usize;
+    let end = block[idx + 1] as usize;
+    // Pairs of (combining code point, composite), sorted by the former
+    let (pairs, _) = COMPOSITION_DATA[start..end].as_chunks::<2>();
+    let i = pairs.binary_search_by_key(&c, |pair| pair[0]).ok()?;
+    char::from_u32(pairs[i][1])
+}
+
 fn sort_marks(input: &mut [u32]) {
     for idx in 1..input.len() {
         let ccc = get_ccc(input[idx]);
@@ -138,111 +346,6 @@ fn sort_marks(input: &mut [u32]) {
     }
 }
 
-fn compose(input: &mut Vec<u32>) {
-    let mut input_count = 0;
-    let mut composition_count = 0;
-
-    while input_count < input.len() {
-        input[composition_count] = input[input_count];
-
-        // Skip null characters that may have been left from decomposition
-        if input[input_count] == 0 {
-            input_count += 1;
-            continue;
-        }
-
-        if input[input_count] >= HANGUL_LBASE && input[input_count] < HANGUL_LBASE + HANGUL_LCOUNT {
-            if input_count + 1 < input.len()
-                && input[input_count + 1] >= HANGUL_VBASE
-                && input[input_count + 1] < HANGUL_VBASE + HANGUL_VCOUNT
-            {
-                input[composition_count] = HANGUL_SBASE
-                    + ((input[input_count] - HANGUL_LBASE) * HANGUL_VCOUNT
-                        + input[input_count + 1]
-                        - HANGUL_VBASE)
-                        * HANGUL_TCOUNT;
-                input_count += 1;
-                if input_count + 1 < input.len()
-                    && input[input_count + 1] > HANGUL_TBASE
-                    && input[input_count + 1] < HANGUL_TBASE + HANGUL_TCOUNT
-                {
-                    input[composition_count] += input[input_count + 1] - HANGUL_TBASE;
-                    input_count += 1;
-                }
-            }
-        } else if input[input_count] >= HANGUL_SBASE
-            && input[input_count] < HANGUL_SBASE + HANGUL_SCOUNT
-        {
-            if (input[input_count] - HANGUL_SBASE) % HANGUL_TCOUNT != 0
-                && input_count + 1 < input.len()
-                && input[input_count + 1] > HANGUL_TBASE
-                && input[input_count + 1] < HANGUL_TBASE + HANGUL_TCOUNT
-            {
-                input[composition_count] += input[input_count + 1] - HANGUL_TBASE;
-                input_count += 1;
-            }
-        } else if input[input_count] < 0x110000 {
-            let ci = COMPOSITION_INDEX[(input[input_count] >> 8) as usize] as usize;
-            let composition_idx = (input[input_count] % 256) as usize;
-            let composition = &COMPOSITION_BLOCK[ci][composition_idx..];
-            let initial_composition_count = composition_count;
-            let mut previous_ccc = -1i32;
-
-            while input_count + 1 < input.len() {
-                let ccc = get_ccc(input[input_count + 1]) as i32;
-
-                if composition.len() >= 2 && composition[1] != composition[0] && previous_ccc < ccc
-                {
-                    // Try finding a composition
-                    let mut left = composition[0] as usize;
-                    let mut right = composition[1] as usize;
-                    while left + 2 < right {
-                        let middle = left + (((right - left) >> 1) & !1);
-                        if COMPOSITION_DATA[middle] <= input[input_count + 1] {
-                            left = middle;
-                        }
-                        if COMPOSITION_DATA[middle] >= input[input_count + 1] {
-                            right = middle;
-                        }
-                    }
-
-                    if left < COMPOSITION_DATA.len()
-                        && COMPOSITION_DATA[left] == input[input_count + 1]
-                        && left + 1 < COMPOSITION_DATA.len()
-                    {
-                        input[initial_composition_count] = COMPOSITION_DATA[left + 1];
-                        let new_ci =
-                            COMPOSITION_INDEX[(COMPOSITION_DATA[left + 1] >> 8) as usize] as usize;
-                        let new_char_idx = (COMPOSITION_DATA[left + 1] % 256) as usize;
-                        if new_ci < COMPOSITION_BLOCK.len()
-                            && new_char_idx < COMPOSITION_BLOCK[new_ci].len()
-                        {
-                            // Update composition reference for potential further composition
-                        }
-                        input_count += 1;
-                        continue;
-                    }
-                }
-
-                if ccc == 0 {
-                    break; // Not a combining character
-                }
-                previous_ccc = ccc;
-                input[composition_count] = input[input_count + 1];
-                composition_count += 1;
-                input_count += 1;
-            }
-        }
-
-        input_count += 1;
-        composition_count += 1;
-    }
-
-    if composition_count < input_count {
-        input.truncate(composition_count);
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -272,9 +375,92 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_canonically_equivalent() {
+        assert!(canonically_equivalent("abc", "abc"));
+        assert!(!canonically_equivalent("abc", "abd"));
+        assert!(!canonically_equivalent("ab", "abc"));
+        // Hangul syllable vs. conjoining jamo
+        assert!(canonically_equivalent(
+            "\u{D55C}",
+            "\u{1112}\u{1161}\u{11AB}"
+        ));
+        // Precomposed vs. decomposed Latin letter
+        assert!(canonically_equivalent("\u{00C0}b", "A\u{0300}b"));
+        assert!(!canonically_equivalent("\u{00C0}", "A\u{0301}"));
+        // Combining marks with different classes commute
+        assert!(canonically_equivalent(
+            "a\u{0323}\u{0301}",
+            "a\u{0301}\u{0323}"
+        ));
+        assert!(canonically_equivalent("\u{1EC7}", "e\u{0302}\u{0323}"));
+        // Marks with the same class do not
+        assert!(!canonically_equivalent(
+            "a\u{0301}\u{0300}",
+            "a\u{0300}\u{0301}"
+        ));
+    }
+
+    #[test]
+    fn test_normalize_multiple_marks() {
+        assert_eq!(normalize("cafe\u{0301}"), "caf\u{00E9}");
+        assert_eq!(normalize("e\u{0323}\u{0302}"), "\u{1EC7}");
+        assert_eq!(normalize("e\u{0302}\u{0323}"), "\u{1EC7}");
+        assert_eq!(normalize("\u{1112}\u{1161}\u{11AB}"), "\u{D55C}");
+        // Compositions added in Unicode 16.0
+        assert_eq!(normalize("\u{16D67}\u{16D67}"), "\u{16D68}");
+        assert_eq!(normalize("\u{105D2}\u{0307}"), "\u{105C9}");
+        // Composition exclusions stay decomposed
+        assert_eq!(normalize("\u{0958}"), "\u{0915}\u{093C}");
+    }
+
+    #[test]
+    fn test_normalize_random_sequences() {
+        let pool: Vec<char> = "aeiouAO\u{E9}\u{1EC7}\u{0301}\u{0302}\u{0323}\u{0308}\u{0345}\u{03B1}\u{1F80}\u{0958}\u{093C}\u{1112}\u{1161}\u{11AB}\u{D55C}\u{AC00}\u{0CC6}\u{0CC2}\u{0CD5}"
+            .chars()
+            .collect();
+        let mut state = 0xDEAD_BEEF_u32;
+        for len in 0..24 {
+            for _ in 0..200 {
+                let input: String = (0..len)
+                    .map(|_| {
+                        state ^= state << 13;
+                        state ^= state >> 17;
+                        state ^= state << 5;
+                        pool[state as usize % pool.len()]
+                    })
+                    .collect();
+                let normalized = normalize(&input);
+                assert!(canonically_equivalent(&normalized, &input), "{input:?}");
+                assert_eq!(normalize(&normalized), normalized, "{input:?}");
+            }
+        }
+
+        // Runs of marks longer than the inline buffer
+        let marks = format!("a{}", "\u{0301}\u{0323}".repeat(20));
+        let normalized = normalize(&marks);
+        assert!(canonically_equivalent(&normalized, &marks));
+        assert_eq!(normalize(&normalized), normalized);
+    }
+
     #[test]
     fn test_hangul_constants() {
         assert_eq!(HANGUL_NCOUNT, 588);
         assert_eq!(HANGUL_SCOUNT, 11172);
     }
+
+    #[test]
+    fn test_segment_start() {
+        let (pairs, _) = COMPOSITION_DATA.as_chunks::<2>();
+        let mut seconds: Vec<u32> = pairs.iter().map(|pair| pair[0]).collect();
+        seconds.sort_unstable();
+        seconds.dedup();
+        assert_eq!(seconds, COMPOSITION_SECONDS);
+
+        assert!(is_segment_start('a'));
+        assert!(is_segment_start('\u{E9}'));
+        assert!(!is_segment_start('\u{301}'));
+        assert!(!is_segment_start('\u{DCF}'));
+        assert!(!is_segment_start('\u{1161}'));
+    }
 }
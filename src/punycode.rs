@@ -1,268 +1,891 @@
-const BASE: i32 = 36;
-const TMIN: i32 = 1;
-const TMAX: i32 = 26;
-const SKEW: i32 = 38;
-const DAMP: i32 = 700;
-const INITIAL_BIAS: i32 = 72;
-const INITIAL_N: u32 = 128;
-
-fn char_to_digit_value(value: u8) -> i32 {
-    match value {
-        b'a'..=b'z' => (value - b'a') as i32,
-        b'0'..=b'9' => (value - b'0') as i32 + 26,
-        _ => -1,
-    }
+use PunycodeErrorKind::*;
+
+/// Number of non-basic code points up to which the encoder counts handled
+/// code points by scanning rather than with a [`Fenwick`] tree.
+const SHORT_INPUT_LEN: usize = 64;
+
+/// Longest Punycode part of an A-label: the 63-octet label cap minus the
+/// `xn--` prefix.
+const MAX_LABEL_PUNYCODE_LEN: usize = 63 - 4;
+
+/// Largest value any Bootstring integer may reach (RFC 3492 §6.4 `maxint`).
+const MAX_INT: u32 = 0x7fff_ffff;
+
+/// The reason a Punycode (or other Bootstring) conversion failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PunycodeErrorKind {
+    /// The input to [`punycode_to_utf32`] starts with the `xn--` ACE prefix.
+    AcePrefix,
+    /// The literal part before the last delimiter contains a non-basic code
+    /// point.
+    NonBasicCodePoint,
+    /// A byte that is not a digit of the alphabet.
+    InvalidDigit,
+    /// The input ends in the middle of a variable-length integer.
+    TruncatedInput,
+    /// An intermediate value exceeds the 31-bit integer range.
+    Overflow,
+    /// A delta decodes to a basic code point, which must be written literally.
+    BasicCodePoint,
+    /// A surrogate or a value above U+10FFFF was passed to the encoder, or
+    /// decoded into a `String`.
+    InvalidCodePoint,
+    /// The input to [`decode_label`] is longer than an A-label allows.
+    InputTooLong,
+    /// Decoding would produce more code points than the requested maximum.
+    OutputTooLong,
 }
 
-fn digit_to_char(digit: i32) -> u8 {
-    if digit < 26 {
-        (digit + 97) as u8
-    } else {
-        (digit + 22) as u8
-    }
+/// A Punycode conversion error and where it occurred.
+///
+/// When decoding, `position` is the byte offset in the input string; when
+/// encoding, it is the index of the offending code point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PunycodeError {
+    pub kind: PunycodeErrorKind,
+    pub position: usize,
 }
 
-fn adapt(mut d: i32, n: i32, firsttime: bool) -> i32 {
-    d = if firsttime { d / DAMP } else { d / 2 };
-    d += d / n;
-    let mut k = 0;
-    while d > ((BASE - TMIN) * TMAX) / 2 {
-        d /= BASE - TMIN;
-        k += BASE;
+impl PunycodeError {
+    fn new(kind: PunycodeErrorKind, position: usize) -> Self {
+        Self { kind, position }
     }
-    k + (((BASE - TMIN + 1) * d) / (d + SKEW))
 }
 
-pub fn punycode_to_utf32(input: &str) -> Option<Vec<u32>> {
-    // See https://github.com/whatwg/url/issues/803
-    if input.starts_with("xn--") {
-        return None;
-    }
+/// A Bootstring encoding (RFC 3492 §3–§6) with its parameters and digit
+/// alphabet.
+///
+/// Punycode is the instance [`PUNYCODE`]; other instances can be built with
+/// [`Bootstring::new`].
+#[derive(Debug, Clone, Copy)]
+pub struct Bootstring {
+    alphabet: &'static [u8],
+    digit_values: [u8; 128],
+    delimiter: u8,
+    tmin: u32,
+    tmax: u32,
+    skew: u32,
+    damp: u32,
+    initial_bias: u32,
+    initial_n: u32,
+}
 
-    let mut written_out = 0i32;
-    let mut out = Vec::with_capacity(input.len().max(16)); // Estimate capacity for better performance
-    let mut n = INITIAL_N;
-    let mut i = 0i32;
-    let mut bias = INITIAL_BIAS;
+/// The Punycode parameters from RFC 3492 §5.
+pub const PUNYCODE: Bootstring = Bootstring::new(
+    b"abcdefghijklmnopqrstuvwxyz0123456789",
+    b'-',
+    1,
+    26,
+    38,
+    700,
+    72,
+    128,
+);
 
-    let mut input_bytes = input.as_bytes();
+impl Bootstring {
+    /// Creates a Bootstring encoding whose base is the length of `alphabet`,
+    /// the digit at index `d` encoding the value `d`.
+    ///
+    /// Code points below `initial_n` are basic and copied literally, so the
+    /// digits and the delimiter must be basic code points and `initial_n` may
+    /// not exceed 0x80.
+    ///
+    /// # Panics
+    ///
+    /// Panics (at compile time when used in a `const`) if the parameters
+    /// violate the constraints of RFC 3492 §4 or the alphabet contains
+    /// duplicate or non-basic digits.
+    #[allow(clippy::too_many_arguments)]
+    pub const fn new(
+        alphabet: &'static [u8],
+        delimiter: u8,
+        tmin: u32,
+        tmax: u32,
+        skew: u32,
+        damp: u32,
+        initial_bias: u32,
+        initial_n: u32,
+    ) -> Self {
+        let base = alphabet.len() as u32;
+        assert!(base >= 2 && base <= 128, "base must be between 2 and 128");
+        assert!(tmin <= tmax && tmax < base, "need tmin <= tmax <= base - 1");
+        assert!(skew >= 1, "skew must be at least 1");
+        assert!(damp >= 2, "damp must be at least 2");
+        assert!(
+            initial_bias % base <= base - tmin,
+            "initial_bias mod base must not exceed base - tmin"
+        );
+        assert!(initial_n <= 0x80, "basic code points must be ASCII");
+        assert!(
+            (delimiter as u32) < initial_n,
+            "delimiter must be a basic code point"
+        );
 
-    // grab ascii content
-    if let Some(end_of_ascii) = input_bytes.iter().rposition(|&b| b == b'-') {
-        for &c in &input_bytes[..end_of_ascii] {
-            if c >= 0x80 {
-                return None;
-            }
-            out.push(c as u32);
-            written_out += 1;
+        let mut digit_values = [u8::MAX; 128];
+        let mut i = 0;
+        while i < alphabet.len() {
+            let digit = alphabet[i];
+            assert!(
+                (digit as u32) < initial_n,
+                "digits must be basic code points"
+            );
+            assert!(digit != delimiter, "the delimiter cannot be a digit");
+            assert!(
+                digit_values[digit as usize] == u8::MAX,
+                "duplicate digit in alphabet"
+            );
+            digit_values[digit as usize] = i as u8;
+            i += 1;
         }
-        input_bytes = &input_bytes[end_of_ascii + 1..];
-    }
 
-    let mut pos = 0;
-    while pos < input_bytes.len() {
-        let oldi = i;
-        let mut w = 1i32;
-        let k = BASE;
-        loop {
-            if pos >= input_bytes.len() {
-                return None;
-            }
-            let code_point = input_bytes[pos];
-            pos += 1;
-            let digit = char_to_digit_value(code_point);
-            if digit < 0 {
-                return None;
-            }
-            if digit > (0x7fffffff - i) / w {
-                return None;
-            }
-            i += digit * w;
-            let t = if k <= bias {
-                TMIN
-            } else if k >= bias + TMAX {
-                TMAX
+        // Letters are case-insensitive digits unless the other case is a
+        // digit of its own (RFC 3492 §5 and Appendix A).
+        let mut i = 0;
+        while i < alphabet.len() {
+            let digit = alphabet[i];
+            let other = if digit.is_ascii_lowercase() {
+                digit.to_ascii_uppercase()
             } else {
-                k - bias
+                digit.to_ascii_lowercase()
             };
-            if digit < t {
-                break;
+            if other != delimiter && digit_values[other as usize] == u8::MAX {
+                digit_values[other as usize] = i as u8;
             }
-            if w > 0x7fffffff / (BASE - t) {
-                return None;
-            }
-            w *= BASE - t;
+            i += 1;
+        }
+
+        Self {
+            alphabet,
+            digit_values,
+            delimiter,
+            tmin,
+            tmax,
+            skew,
+            damp,
+            initial_bias,
+            initial_n,
+        }
+    }
+
+    pub fn base(&self) -> u32 {
+        self.alphabet.len() as u32
+    }
+
+    pub fn alphabet(&self) -> &'static [u8] {
+        self.alphabet
+    }
+
+    pub fn delimiter(&self) -> u8 {
+        self.delimiter
+    }
+
+    pub fn tmin(&self) -> u32 {
+        self.tmin
+    }
+
+    pub fn tmax(&self) -> u32 {
+        self.tmax
+    }
+
+    pub fn skew(&self) -> u32 {
+        self.skew
+    }
+
+    pub fn damp(&self) -> u32 {
+        self.damp
+    }
+
+    pub fn initial_bias(&self) -> u32 {
+        self.initial_bias
+    }
+
+    pub fn initial_n(&self) -> u32 {
+        self.initial_n
+    }
+
+    fn digit_value(&self, value: u8) -> Option<u32> {
+        match self.digit_values.get(value as usize) {
+            Some(&d) if d != u8::MAX => Some(d as u32),
+            _ => None,
         }
-        bias = adapt(i - oldi, written_out + 1, oldi == 0);
-        if i / (written_out + 1) > (0x7fffffff - n as i32) {
-            return None;
+    }
+
+    /// Returns the digit for `digit`, forced to upper or lower case if
+    /// `uppercase` is given and the alphabet allows it.
+    fn digit_to_char(&self, digit: u32, uppercase: Option<bool>) -> u8 {
+        let c = self.alphabet[digit as usize];
+        let cased = match uppercase {
+            None => return c,
+            Some(true) => c.to_ascii_uppercase(),
+            Some(false) => c.to_ascii_lowercase(),
+        };
+        if self.digit_value(cased) == Some(digit) {
+            cased
+        } else {
+            c
         }
-        n += (i / (written_out + 1)) as u32;
-        i %= written_out + 1;
-        if n < 0x80 {
-            return None;
+    }
+
+    fn threshold(&self, k: u32, bias: u32) -> u32 {
+        if k <= bias {
+            self.tmin
+        } else if k >= bias + self.tmax {
+            self.tmax
+        } else {
+            k - bias
         }
-        // Optimize: avoid O(n) Vec::insert by growing and shifting manually
-        let insert_pos = i as usize;
-        out.push(0); // Reserve space
-        let len = out.len();
-        if insert_pos < len - 1 {
-            // Shift elements to the right
-            out.copy_within(insert_pos..len - 1, insert_pos + 1);
+    }
+
+    fn adapt(&self, mut delta: u32, num_points: u32, first_time: bool) -> u32 {
+        let base = self.base();
+        delta = if first_time {
+            delta / self.damp
+        } else {
+            delta / 2
+        };
+        delta += delta / num_points;
+        let mut k = 0;
+        while delta > ((base - self.tmin) * self.tmax) / 2 {
+            delta /= base - self.tmin;
+            k += base;
         }
-        out[insert_pos] = n;
-        written_out += 1;
-        i += 1;
+        k + (((base - self.tmin + 1) * delta) / (delta + self.skew))
     }
 
-    Some(out)
-}
+    /// Decodes a Bootstring string into code points.
+    ///
+    /// Digits are accepted in either case.
+    pub fn decode(&self, input: &str) -> Result<Vec<u32>, PunycodeError> {
+        let mut sink = CodePointSink {
+            out: Vec::with_capacity(input.len().max(16)), // Estimate capacity for better performance
+            case_flags: None,
+        };
+        self.decode_into(input, usize::MAX, &mut sink)?;
+        Ok(sink.out)
+    }
 
-pub fn verify_punycode(input: &str) -> bool {
-    if input.starts_with("xn--") {
-        return false;
+    /// Like [`decode`](Self::decode), but fails with
+    /// [`PunycodeErrorKind::OutputTooLong`] as soon as the output would
+    /// exceed `max_len` code points.
+    ///
+    /// Decoding stops at the offending digit, so the work done is bounded by
+    /// `max_len` rather than by the length of the input.
+    pub fn decode_with_limit(
+        &self,
+        input: &str,
+        max_len: usize,
+    ) -> Result<Vec<u32>, PunycodeError> {
+        let mut sink = CodePointSink {
+            out: Vec::with_capacity(input.len().min(max_len)),
+            case_flags: None,
+        };
+        self.decode_into(input, max_len, &mut sink)?;
+        Ok(sink.out)
+    }
+
+    /// Decodes a Bootstring string into code points and their case flags
+    /// (RFC 3492 Appendix A).
+    ///
+    /// The flag of a basic code point is `true` if it is an uppercase letter;
+    /// the flag of a decoded code point is `true` if the last digit of its
+    /// delta is an uppercase letter.
+    pub fn decode_with_case(&self, input: &str) -> Result<(Vec<u32>, Vec<bool>), PunycodeError> {
+        let mut case_flags = Vec::new();
+        let mut sink = CodePointSink {
+            out: Vec::with_capacity(input.len().max(16)),
+            case_flags: Some(&mut case_flags),
+        };
+        self.decode_into(input, usize::MAX, &mut sink)?;
+        Ok((sink.out, case_flags))
+    }
+
+    /// Decodes a Bootstring string, appending the result to `out`.
+    ///
+    /// On error `out` is left unchanged.
+    pub fn decode_to_string(&self, input: &str, out: &mut String) -> Result<(), PunycodeError> {
+        let mut chars = vec!['\0'; input.len()];
+        let mut sink = CharSink {
+            chars: &mut chars,
+            len: 0,
+        };
+        self.decode_into(input, usize::MAX, &mut sink)?;
+        out.extend(sink.decoded());
+        Ok(())
     }
 
-    let mut written_out = 0usize;
-    let mut n = INITIAL_N;
-    let mut i = 0i32;
-    let mut bias = INITIAL_BIAS;
+    /// Decoder core driven by every decoding function and by
+    /// [`verify`](Self::verify); `sink` decides what happens to the output.
+    fn decode_into<S: DecodeSink>(
+        &self,
+        input: &str,
+        max_len: usize,
+        sink: &mut S,
+    ) -> Result<(), PunycodeError> {
+        let base = self.base();
+        let mut written_out = 0u32;
+        let mut n = self.initial_n;
+        let mut i = 0u32;
+        let mut bias = self.initial_bias;
 
-    let mut input_bytes = input.as_bytes();
+        let input_bytes = input.as_bytes();
+        let mut pos = 0;
 
-    // grab ascii content
-    if let Some(end_of_ascii) = input_bytes.iter().rposition(|&b| b == b'-') {
-        for &c in &input_bytes[..end_of_ascii] {
-            if c >= 0x80 {
-                return false;
+        // grab basic content
+        if let Some(end_of_basic) = input_bytes.iter().rposition(|&b| b == self.delimiter) {
+            for (position, &c) in input_bytes[..end_of_basic].iter().enumerate() {
+                if c as u32 >= self.initial_n {
+                    return Err(PunycodeError::new(NonBasicCodePoint, position));
+                }
+                if position >= max_len {
+                    return Err(PunycodeError::new(OutputTooLong, position));
+                }
+                sink.push_basic(c);
+                written_out += 1;
             }
-            written_out += 1;
+            pos = end_of_basic + 1;
         }
-        input_bytes = &input_bytes[end_of_ascii + 1..];
-    }
 
-    let mut pos = 0;
-    while pos < input_bytes.len() {
-        let oldi = i;
-        let mut w = 1i32;
-        let k = BASE;
-        loop {
-            if pos >= input_bytes.len() {
-                return false;
+        while pos < input_bytes.len() {
+            if written_out as usize >= max_len {
+                return Err(PunycodeError::new(OutputTooLong, pos));
             }
-            let code_point = input_bytes[pos];
-            pos += 1;
-            let digit = char_to_digit_value(code_point);
-            if digit < 0 {
-                return false;
+            let oldi = i;
+            let mut w = 1u32;
+            let mut k = base;
+            loop {
+                if pos >= input_bytes.len() {
+                    return Err(PunycodeError::new(TruncatedInput, pos));
+                }
+                let digit = self
+                    .digit_value(input_bytes[pos])
+                    .ok_or(PunycodeError::new(InvalidDigit, pos))?;
+                pos += 1;
+                if digit > (MAX_INT - i) / w {
+                    return Err(PunycodeError::new(Overflow, pos - 1));
+                }
+                i += digit * w;
+                let t = self.threshold(k, bias);
+                if digit < t {
+                    break;
+                }
+                if w > MAX_INT / (base - t) {
+                    return Err(PunycodeError::new(Overflow, pos - 1));
+                }
+                w *= base - t;
+                k += base;
             }
-            if digit > (0x7fffffff - i) / w {
-                return false;
+            bias = self.adapt(i - oldi, written_out + 1, oldi == 0);
+            if i / (written_out + 1) > MAX_INT - n {
+                return Err(PunycodeError::new(Overflow, pos - 1));
             }
-            i += digit * w;
-            let t = if k <= bias {
-                TMIN
-            } else if k >= bias + TMAX {
-                TMAX
-            } else {
-                k - bias
-            };
-            if digit < t {
-                break;
+            n += i / (written_out + 1);
+            i %= written_out + 1;
+            if n < self.initial_n {
+                return Err(PunycodeError::new(BasicCodePoint, pos - 1));
             }
-            if w > 0x7fffffff / (BASE - t) {
-                return false;
+            let uppercase = input_bytes[pos - 1].is_ascii_uppercase();
+            if !sink.insert(i as usize, n, uppercase) {
+                return Err(PunycodeError::new(InvalidCodePoint, pos - 1));
             }
-            w *= BASE - t;
-        }
-        bias = adapt(i - oldi, (written_out + 1) as i32, oldi == 0);
-        if i / (written_out + 1) as i32 > (0x7fffffff_u32 - n) as i32 {
-            return false;
+            written_out += 1;
+            i += 1;
         }
-        n += (i / (written_out + 1) as i32) as u32;
-        i %= (written_out + 1) as i32;
-        if n < 0x80 {
-            return false;
+
+        Ok(())
+    }
+
+    /// Returns `true` if `input` is a valid Bootstring string, without
+    /// producing the decoded code points.
+    pub fn verify(&self, input: &str) -> bool {
+        self.decode_into(input, usize::MAX, &mut Validator).is_ok()
+    }
+
+    /// Encodes code points into a Bootstring string.
+    ///
+    /// Fails for surrogates, values above U+10FFFF and on overflow.
+    pub fn encode(&self, input: &[u32]) -> Result<String, PunycodeError> {
+        let mut out = String::new();
+        self.encode_into(input.iter().copied(), None, &mut out)?;
+        Ok(out)
+    }
+
+    /// Encodes code points into a Bootstring string with mixed-case
+    /// annotations (RFC 3492 Appendix A).
+    ///
+    /// Basic letters are written in the case given by their flag, and the last
+    /// digit of every other code point's delta is uppercased if its flag is
+    /// set, so that [`decode_with_case`](Self::decode_with_case) returns the
+    /// same flags.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `case_flags` and `input` differ in length.
+    pub fn encode_with_case(
+        &self,
+        input: &[u32],
+        case_flags: &[bool],
+    ) -> Result<String, PunycodeError> {
+        assert_eq!(
+            input.len(),
+            case_flags.len(),
+            "one case flag is needed per code point"
+        );
+        let mut out = String::new();
+        self.encode_into(input.iter().copied(), Some(case_flags), &mut out)?;
+        Ok(out)
+    }
+
+    /// Encodes a string, appending the result to `out`.
+    ///
+    /// On error `out` is left unchanged; the error position is a `char` index.
+    pub fn encode_str(&self, input: &str, out: &mut String) -> Result<(), PunycodeError> {
+        self.encode_chars(input.chars(), out)
+    }
+
+    /// Encodes a sequence of `char`s, appending the result to `out`.
+    ///
+    /// On error `out` is left unchanged.
+    pub fn encode_chars<I>(&self, input: I, out: &mut String) -> Result<(), PunycodeError>
+    where
+        I: IntoIterator<Item = char>,
+    {
+        let start = out.len();
+        let result = self.encode_into(input.into_iter().map(u32::from), None, out);
+        if result.is_err() {
+            out.truncate(start);
         }
-        written_out += 1;
-        i += 1;
+        result
     }
 
-    true
-}
+    /// Returns the length of the Bootstring encoding of `input` without
+    /// producing it, or `None` if encoding would fail.
+    ///
+    /// Unlike the encoder this does not allocate; it rescans the input once
+    /// per distinct non-basic code point, which is cheap for labels.
+    pub fn encoded_len<I>(&self, input: I) -> Option<usize>
+    where
+        I: IntoIterator<Item = u32>,
+        I::IntoIter: Clone,
+    {
+        let input = input.into_iter();
+        let base = self.base();
+        let mut n = self.initial_n;
+        let mut d = 0u32;
+        let mut bias = self.initial_bias;
+        let mut h = 0u32;
+        let mut input_len = 0u32;
 
-pub fn utf32_to_punycode(input: &[u32]) -> Option<String> {
-    let mut out = Vec::new();
-    let mut n = INITIAL_N;
-    let mut d = 0i32;
-    let mut bias = INITIAL_BIAS;
-    let mut h = 0usize;
-
-    // first push the ascii content
-    for &c in input {
-        if c < 0x80 {
-            h += 1;
-            out.push(c as u8);
+        for c in input.clone() {
+            if c > 0x10ffff || (0xd800..0xe000).contains(&c) {
+                return None;
+            }
+            if c < self.initial_n {
+                h += 1;
+            }
+            input_len = input_len.checked_add(1)?;
         }
-        if c > 0x10ffff || (0xd800..0xe000).contains(&c) {
-            return None;
+        let b = h;
+        let mut len = b as usize + usize::from(b > 0);
+
+        while h < input_len {
+            let m = input.clone().filter(|&c| c >= n).min()?;
+            if (m - n) > (MAX_INT - d) / (h + 1) {
+                return None;
+            }
+            d += (m - n) * (h + 1);
+            n = m;
+            for c in input.clone() {
+                if c < n {
+                    d += 1;
+                    if d > MAX_INT {
+                        return None;
+                    }
+                } else if c == n {
+                    let mut q = d;
+                    let mut k = base;
+                    loop {
+                        let t = self.threshold(k, bias);
+                        if q < t {
+                            break;
+                        }
+                        len += 1;
+                        q = (q - t) / (base - t);
+                        k += base;
+                    }
+                    len += 1;
+                    bias = self.adapt(d, h + 1, h == b);
+                    d = 0;
+                    h += 1;
+                }
+            }
+            if d >= MAX_INT {
+                return None;
+            }
+            d += 1;
+            n += 1;
         }
-    }
-    let b = h;
-    if b > 0 {
-        out.push(b'-');
+
+        Some(len)
     }
 
-    while h < input.len() {
-        let mut m = 0x10FFFF;
-        for &code_point in input {
-            if code_point >= n && code_point < m {
-                m = code_point;
+    /// Encoder core. Rather than rescanning the input for the next smallest
+    /// code point and again for every delta (O(n²)), the non-basic code points
+    /// are sorted once and the number of already handled code points before
+    /// each occurrence is kept in a Fenwick tree, for O(n log n) overall.
+    fn encode_into<I>(
+        &self,
+        input: I,
+        case_flags: Option<&[bool]>,
+        out: &mut String,
+    ) -> Result<(), PunycodeError>
+    where
+        I: Iterator<Item = u32>,
+    {
+        let base = self.base();
+        let mut n = self.initial_n;
+        let mut d = 0u32;
+        let mut bias = self.initial_bias;
+        let mut h = 0u32;
+        let mut input_len = 0usize;
+        let mut non_basic = Vec::new();
+
+        // first push the basic content
+        for (position, c) in input.enumerate() {
+            if c < self.initial_n {
+                h += 1;
+                let c = c as u8;
+                out.push(char::from(match case_flags {
+                    Some(flags) if flags[position] => c.to_ascii_uppercase(),
+                    Some(_) => c.to_ascii_lowercase(),
+                    None => c,
+                }));
+            } else {
+                non_basic.push((c, position));
+            }
+            if c > 0x10ffff || (0xd800..0xe000).contains(&c) {
+                return Err(PunycodeError::new(InvalidCodePoint, position));
             }
+            input_len += 1;
         }
-
-        if (m - n) > ((0x7fffffff_u32 - d as u32) / (h + 1) as u32) {
-            return None;
+        let b = h;
+        if b > 0 {
+            out.push(char::from(self.delimiter));
         }
-        d += ((m - n) * (h + 1) as u32) as i32;
-        n = m;
+        if non_basic.is_empty() {
+            return Ok(());
+        }
+
+        // Basic code points are handled before the first delta. Short labels
+        // skip the tree and count unhandled code points directly instead.
+        let mut handled = (non_basic.len() > SHORT_INPUT_LEN).then(|| {
+            let mut handled = Fenwick::new(input_len);
+            let mut next_non_basic = non_basic.iter().map(|&(_, p)| p).peekable();
+            for position in 0..input_len {
+                if next_non_basic.next_if_eq(&position).is_none() {
+                    handled.add(position);
+                }
+            }
+            handled
+        });
 
-        for &c in input {
-            if c < n {
-                if d == 0x7fffffff {
-                    return None;
+        // Occurrences of each code point, in increasing code point then
+        // position order.
+        non_basic.sort_unstable();
+
+        let mut rest = &non_basic[..];
+        while let Some(&(m, first)) = rest.first() {
+            // Code points in `rest` are exactly the ones not handled yet.
+            let unhandled = rest;
+            let count_handled = |start: usize, end: usize| match &handled {
+                Some(handled) => handled.count(start, end),
+                None => {
+                    let pending = unhandled
+                        .iter()
+                        .filter(|&&(_, p)| (start..end).contains(&p))
+                        .count();
+                    (end - start - pending) as u32
                 }
-                d += 1;
+            };
+            let count = rest.iter().take_while(|&&(c, _)| c == m).count();
+            let (group, tail) = rest.split_at(count);
+            rest = tail;
+
+            if (m - n) > (MAX_INT - d) / (h + 1) {
+                return Err(PunycodeError::new(Overflow, first));
             }
-            if c == n {
+            d += (m - n) * (h + 1);
+            n = m;
+
+            let mut scanned = 0;
+            for &(_, position) in group {
+                // Every code point below n between the previous occurrence and
+                // this one increments the delta.
+                d = d
+                    .checked_add(count_handled(scanned, position))
+                    .filter(|&d| d <= MAX_INT)
+                    .ok_or(PunycodeError::new(Overflow, position))?;
+                scanned = position + 1;
+
                 let mut q = d;
-                let k = BASE;
+                let mut k = base;
                 loop {
-                    let t = if k <= bias {
-                        TMIN
-                    } else if k >= bias + TMAX {
-                        TMAX
-                    } else {
-                        k - bias
-                    };
-
+                    let t = self.threshold(k, bias);
                     if q < t {
                         break;
                     }
-                    out.push(digit_to_char(t + ((q - t) % (BASE - t))));
-                    q = (q - t) / (BASE - t);
+                    out.push(char::from(
+                        self.digit_to_char(t + ((q - t) % (base - t)), None),
+                    ));
+                    q = (q - t) / (base - t);
+                    k += base;
                 }
-                out.push(digit_to_char(q));
-                bias = adapt(d, (h + 1) as i32, h == b);
+                let uppercase = case_flags.map(|flags| flags[position]);
+                out.push(char::from(self.digit_to_char(q, uppercase)));
+                bias = self.adapt(d, h + 1, h == b);
                 d = 0;
                 h += 1;
             }
+            let last = group[group.len() - 1].1;
+            d = d
+                .checked_add(count_handled(scanned, input_len))
+                .filter(|&d| d < MAX_INT)
+                .ok_or(PunycodeError::new(Overflow, last))?;
+            if let Some(handled) = &mut handled {
+                for &(_, position) in group {
+                    handled.add(position);
+                }
+            }
+            d += 1;
+            n += 1;
+        }
+
+        Ok(())
+    }
+}
+
+/// Fenwick (binary indexed) tree counting marked positions.
+struct Fenwick {
+    tree: Vec<u32>,
+}
+
+impl Fenwick {
+    fn new(len: usize) -> Self {
+        Self {
+            tree: vec![0; len + 1],
+        }
+    }
+
+    fn add(&mut self, position: usize) {
+        let mut i = position + 1;
+        while i < self.tree.len() {
+            self.tree[i] += 1;
+            i += i & i.wrapping_neg();
+        }
+    }
+
+    /// Number of marked positions before `end`.
+    fn prefix(&self, end: usize) -> u32 {
+        let mut sum = 0;
+        let mut i = end;
+        while i > 0 {
+            sum += self.tree[i];
+            i -= i & i.wrapping_neg();
+        }
+        sum
+    }
+
+    /// Number of marked positions in `start..end`.
+    fn count(&self, start: usize, end: usize) -> u32 {
+        self.prefix(end) - self.prefix(start)
+    }
+}
+
+/// Receives the output of [`Bootstring::decode_into`].
+trait DecodeSink {
+    /// Appends a code point of the literal part.
+    fn push_basic(&mut self, c: u8);
+
+    /// Inserts a decoded code point at `index`; returns `false` if the sink
+    /// cannot represent it.
+    fn insert(&mut self, index: usize, code_point: u32, uppercase: bool) -> bool;
+}
+
+/// Discards the output; used to validate without decoding.
+struct Validator;
+
+impl DecodeSink for Validator {
+    fn push_basic(&mut self, _c: u8) {}
+
+    fn insert(&mut self, _index: usize, _code_point: u32, _uppercase: bool) -> bool {
+        true
+    }
+}
+
+struct CodePointSink<'a> {
+    out: Vec<u32>,
+    case_flags: Option<&'a mut Vec<bool>>,
+}
+
+impl DecodeSink for CodePointSink<'_> {
+    fn push_basic(&mut self, c: u8) {
+        self.out.push(c as u32);
+        if let Some(flags) = self.case_flags.as_deref_mut() {
+            flags.push(c.is_ascii_uppercase());
+        }
+    }
+
+    fn insert(&mut self, index: usize, code_point: u32, uppercase: bool) -> bool {
+        // Optimize: avoid O(n) Vec::insert by growing and shifting manually
+        self.out.push(0); // Reserve space
+        let len = self.out.len();
+        if index < len - 1 {
+            // Shift elements to the right
+            self.out.copy_within(index..len - 1, index + 1);
         }
-        d += 1;
-        n += 1;
+        self.out[index] = code_point;
+        if let Some(flags) = self.case_flags.as_deref_mut() {
+            flags.insert(index, uppercase);
+        }
+        true
+    }
+}
+
+/// Decodes into a buffer of `char`s that the caller copies out once decoding
+/// succeeds. Every decoded code point consumes at least one input byte, so a
+/// buffer as long as the input always suffices.
+struct CharSink<'a> {
+    chars: &'a mut [char],
+    len: usize,
+}
+
+impl CharSink<'_> {
+    fn decoded(&self) -> &[char] {
+        &self.chars[..self.len]
+    }
+}
+
+impl DecodeSink for CharSink<'_> {
+    fn push_basic(&mut self, c: u8) {
+        self.chars[self.len] = char::from(c);
+        self.len += 1;
+    }
+
+    fn insert(&mut self, index: usize, code_point: u32, _uppercase: bool) -> bool {
+        let Some(c) = char::from_u32(code_point) else {
+            return false;
+        };
+        self.chars.copy_within(index..self.len, index + 1);
+        self.chars[index] = c;
+        self.len += 1;
+        true
     }
+}
 
-    String::from_utf8(out).ok()
+pub fn punycode_to_utf32(input: &str) -> Result<Vec<u32>, PunycodeError> {
+    // See https://github.com/whatwg/url/issues/803
+    if input.starts_with("xn--") {
+        return Err(PunycodeError::new(AcePrefix, 0));
+    }
+    PUNYCODE.decode(input)
+}
+
+pub fn verify_punycode(input: &str) -> bool {
+    if input.starts_with("xn--") {
+        return false;
+    }
+    PUNYCODE.verify(input)
+}
+
+pub fn utf32_to_punycode(input: &[u32]) -> Result<String, PunycodeError> {
+    PUNYCODE.encode(input)
+}
+
+/// Returns the length of the Punycode encoding of `input`, or `None` if it
+/// cannot be encoded. See [`Bootstring::encoded_len`].
+pub fn punycode_encoded_len(input: &[u32]) -> Option<usize> {
+    PUNYCODE.encoded_len(input.iter().copied())
+}
+
+/// Like [`punycode_encoded_len`], for a string.
+pub fn punycode_encoded_len_str(input: &str) -> Option<usize> {
+    PUNYCODE.encoded_len(input.chars().map(u32::from))
+}
+
+/// Decodes Punycode into `out`. See [`Bootstring::decode_to_string`].
+pub fn decode_to_string(input: &str, out: &mut String) -> Result<(), PunycodeError> {
+    // See https://github.com/whatwg/url/issues/803
+    if input.starts_with("xn--") {
+        return Err(PunycodeError::new(AcePrefix, 0));
+    }
+    PUNYCODE.decode_to_string(input, out)
+}
+
+/// Decodes the Punycode part of an A-label (the label without its `xn--`
+/// prefix) into `out`.
+///
+/// Inputs longer than the 59 bytes left by the 63-octet label cap are
+/// rejected before any decoding work is done.
+pub fn decode_label(input: &str, out: &mut String) -> Result<(), PunycodeError> {
+    if input.len() > MAX_LABEL_PUNYCODE_LEN {
+        return Err(PunycodeError::new(InputTooLong, MAX_LABEL_PUNYCODE_LEN));
+    }
+    if input.starts_with("xn--") {
+        return Err(PunycodeError::new(AcePrefix, 0));
+    }
+    let mut chars = ['\0'; MAX_LABEL_PUNYCODE_LEN];
+    let mut sink = CharSink {
+        chars: &mut chars,
+        len: 0,
+    };
+    PUNYCODE.decode_into(input, MAX_LABEL_PUNYCODE_LEN, &mut sink)?;
+    out.extend(sink.decoded());
+    Ok(())
+}
+
+/// Decodes Punycode into at most `max_len` code points. See
+/// [`Bootstring::decode_with_limit`].
+pub fn punycode_to_utf32_with_limit(
+    input: &str,
+    max_len: usize,
+) -> Result<Vec<u32>, PunycodeError> {
+    // See https://github.com/whatwg/url/issues/803
+    if input.starts_with("xn--") {
+        return Err(PunycodeError::new(AcePrefix, 0));
+    }
+    PUNYCODE.decode_with_limit(input, max_len)
+}
+
+/// Encodes a string as Punycode into `out`. See [`Bootstring::encode_str`].
+pub fn encode_str(input: &str, out: &mut String) -> Result<(), PunycodeError> {
+    PUNYCODE.encode_str(input, out)
+}
+
+/// Encodes a sequence of `char`s as Punycode into `out`. See
+/// [`Bootstring::encode_chars`].
+pub fn encode_chars<I>(input: I, out: &mut String) -> Result<(), PunycodeError>
+where
+    I: IntoIterator<Item = char>,
+{
+    PUNYCODE.encode_chars(input, out)
+}
+
+/// Like [`punycode_to_utf32`], also returning the mixed-case annotation of
+/// every code point. See [`Bootstring::decode_with_case`].
+pub fn punycode_to_utf32_with_case(input: &str) -> Result<(Vec<u32>, Vec<bool>), PunycodeError> {
+    // See https://github.com/whatwg/url/issues/803
+    if input.starts_with("xn--") {
+        return Err(PunycodeError::new(AcePrefix, 0));
+    }
+    PUNYCODE.decode_with_case(input)
+}
+
+/// Like [`utf32_to_punycode`], emitting mixed-case annotations. See
+/// [`Bootstring::encode_with_case`].
+pub fn utf32_to_punycode_with_case(
+    input: &[u32],
+    case_flags: &[bool],
+) -> Result<String, PunycodeError> {
+    PUNYCODE.encode_with_case(input, case_flags)
 }
 
 #[cfg(test)]
@@ -273,7 +896,7 @@ mod tests {
     fn test_punycode_encoding() {
         let input = vec![0x00E4];
         let result = utf32_to_punycode(&input);
-        assert!(result.is_some());
+        assert!(result.is_ok());
         let encoded = result.unwrap();
         assert_eq!(encoded, "4ca");
     }
@@ -283,7 +906,7 @@ mod tests {
         let input = vec![0x00E4];
         let encoded = utf32_to_punycode(&input).unwrap();
         let decoded = punycode_to_utf32(&encoded);
-        assert_eq!(decoded, Some(vec![0x00E4]));
+        assert_eq!(decoded, Ok(vec![0x00E4]));
     }
 
     #[test]
@@ -293,10 +916,222 @@ mod tests {
         assert!(verify_punycode(""));
     }
 
+    #[test]
+    fn test_punycode_multi_digit_deltas() {
+        // RFC 3492 §7.1 (A) Arabic (Egyptian)
+        let arabic = [
+            0x0644, 0x064A, 0x0647, 0x0645, 0x0627, 0x0628, 0x062A, 0x0643, 0x0644, 0x0645, 0x0648,
+            0x0634, 0x0639, 0x0631, 0x0628, 0x064A, 0x061F,
+        ];
+        assert_eq!(
+            utf32_to_punycode(&arabic).as_deref(),
+            Ok("egbpdaj6bu4bxfgehfvwxn")
+        );
+        assert_eq!(
+            punycode_to_utf32("egbpdaj6bu4bxfgehfvwxn").as_deref(),
+            Ok(&arabic[..])
+        );
+        assert_eq!(
+            utf32_to_punycode(&[0x4F8B, 0x5B50]).as_deref(),
+            Ok("fsqu00a")
+        );
+    }
+
+    #[test]
+    fn test_custom_bootstring() {
+        const HEX: Bootstring = Bootstring::new(b"0123456789ABCDEF", b'_', 1, 10, 20, 100, 8, 128);
+        assert_eq!(HEX.base(), 16);
+        for input in [
+            &[0x00E4][..],
+            &[0x61, 0x62, 0x4F8B, 0x5B50],
+            &[0x10000, 0x80],
+        ] {
+            let encoded = HEX.encode(input).unwrap();
+            assert!(
+                encoded
+                    .bytes()
+                    .all(|b| b.is_ascii_hexdigit() || b.is_ascii_lowercase() || b == b'_')
+            );
+            assert!(HEX.verify(&encoded));
+            assert_eq!(HEX.decode(&encoded).as_deref(), Ok(input));
+        }
+        assert_eq!(HEX.decode("g"), Err(PunycodeError::new(InvalidDigit, 0)));
+    }
+
+    #[test]
+    fn test_punycode_error_positions() {
+        let decode_err = |input| punycode_to_utf32(input).unwrap_err();
+        let err = PunycodeError::new;
+        assert_eq!(decode_err("ab\u{e9}-4ca"), err(NonBasicCodePoint, 2));
+        assert_eq!(decode_err("abc-4c!"), err(InvalidDigit, 6));
+        assert_eq!(decode_err("abc-4c"), err(TruncatedInput, 6));
+        assert_eq!(decode_err("99999999999"), err(Overflow, 7));
+
+        let encode_err = |input: &[u32]| utf32_to_punycode(input).unwrap_err();
+        assert_eq!(encode_err(&[0x61, 0xD800]), err(InvalidCodePoint, 1));
+        assert_eq!(encode_err(&[0x110000]), err(InvalidCodePoint, 0));
+    }
+
+    #[test]
+    fn test_mixed_case_annotations() {
+        // RFC 3492 §7.1 (L) 3<nen>B<gumi><kinpachi><sensei>
+        let input = [
+            0x0033, 0x5E74, 0x0042, 0x7D44, 0x91D1, 0x516B, 0x5148, 0x751F,
+        ];
+        let flags = [false, false, true, false, false, false, false, false];
+        let encoded = utf32_to_punycode_with_case(&input, &flags).unwrap();
+        assert_eq!(encoded, "3B-ww4c5e180e575a65lsy2b");
+        assert_eq!(
+            punycode_to_utf32_with_case(&encoded),
+            Ok((input.to_vec(), flags.to_vec()))
+        );
+
+        // Uppercase digits on a non-basic code point
+        let encoded = utf32_to_punycode_with_case(&[0x41, 0xE4], &[true, true]).unwrap();
+        assert_eq!(encoded, "A-0fA");
+        assert_eq!(
+            punycode_to_utf32_with_case(&encoded),
+            Ok((vec![0x41, 0xE4], vec![true, true]))
+        );
+
+        // Decoding without flags accepts either case
+        assert_eq!(punycode_to_utf32("4CA"), Ok(vec![0xE4]));
+        assert!(verify_punycode("BCHER-KVA"));
+    }
+
+    #[test]
+    fn test_str_and_char_apis() {
+        let mut out = String::from("xn--");
+        encode_str("bücher", &mut out).unwrap();
+        assert_eq!(out, "xn--bcher-kva");
+
+        out.clear();
+        encode_chars("例子".chars().rev().collect::<Vec<_>>(), &mut out).unwrap();
+        assert_eq!(out, PUNYCODE.encode(&[0x5B50, 0x4F8B]).unwrap());
+
+        let mut decoded = String::from("prefix:");
+        decode_to_string("bcher-kva", &mut decoded).unwrap();
+        assert_eq!(decoded, "prefix:bücher");
+
+        decoded.clear();
+        decode_to_string("egbpdaj6bu4bxfgehfvwxn", &mut decoded).unwrap();
+        assert_eq!(
+            decoded,
+            "\u{0644}\u{064A}\u{0647}\u{0645}\u{0627}\u{0628}\u{062A}\u{0643}\u{0644}\u{0645}\u{0648}\u{0634}\u{0639}\u{0631}\u{0628}\u{064A}\u{061F}"
+        );
+
+        // Long inputs, with insertions all over the output
+        let long: String = (0..3000u32)
+            .map(|i| char::from_u32(0x61 + i % 26 + (i % 7) * 0x3A0).unwrap())
+            .collect();
+        let mut encoded = String::new();
+        encode_str(&long, &mut encoded).unwrap();
+        decoded.clear();
+        decode_to_string(&encoded, &mut decoded).unwrap();
+        assert!(decoded == long);
+
+        // Errors leave the output buffer untouched
+        let mut out = String::from("keep");
+        assert!(decode_to_string("abc-4c!", &mut out).is_err());
+        assert!(decode_to_string("xn--abc", &mut out).is_err());
+        assert_eq!(out, "keep");
+        // U+110000 cannot be stored in a String
+        assert_eq!(punycode_to_utf32("en32g"), Ok(vec![0x110000]));
+        assert_eq!(
+            decode_to_string("en32g", &mut out),
+            Err(PunycodeError::new(InvalidCodePoint, 4))
+        );
+        assert_eq!(out, "keep");
+    }
+
+    #[test]
+    fn test_bounded_decoding() {
+        assert_eq!(
+            PUNYCODE.decode_with_limit("bcher-kva", 6),
+            Ok(vec![0x62, 0xFC, 0x63, 0x68, 0x65, 0x72])
+        );
+        assert_eq!(
+            PUNYCODE.decode_with_limit("bcher-kva", 5),
+            Err(PunycodeError::new(OutputTooLong, 6))
+        );
+        assert_eq!(
+            PUNYCODE.decode_with_limit("abc-", 2),
+            Err(PunycodeError::new(OutputTooLong, 2))
+        );
+
+        // Every "a" is a zero delta inserting one more code point
+        let hostile = "a".repeat(100_000);
+        assert_eq!(
+            PUNYCODE.decode_with_limit(&hostile, 63),
+            Err(PunycodeError::new(OutputTooLong, 63))
+        );
+
+        let mut out = String::new();
+        decode_label("bcher-kva", &mut out).unwrap();
+        assert_eq!(out, "bücher");
+        assert_eq!(
+            decode_label(&hostile, &mut out),
+            Err(PunycodeError::new(InputTooLong, 59))
+        );
+        assert!(decode_label(&"a".repeat(59), &mut out).is_ok());
+        assert_eq!(
+            decode_label("xn--bcher-kva", &mut out),
+            Err(PunycodeError::new(AcePrefix, 0))
+        );
+
+        assert_eq!(
+            punycode_to_utf32_with_limit("bcher-kva", 6),
+            Ok(vec![0x62, 0xFC, 0x63, 0x68, 0x65, 0x72])
+        );
+        assert_eq!(
+            punycode_to_utf32_with_limit("bcher-kva", 5).map_err(|e| e.kind),
+            Err(OutputTooLong)
+        );
+        assert_eq!(
+            punycode_to_utf32_with_limit("xn--bcher-kva", 6),
+            Err(PunycodeError::new(AcePrefix, 0))
+        );
+    }
+
+    #[test]
+    fn test_encoded_len() {
+        assert_eq!(punycode_encoded_len(&[]), Some(0));
+        assert_eq!(punycode_encoded_len_str("abc"), Some(4));
+        assert_eq!(punycode_encoded_len_str("bücher"), Some(9));
+        assert_eq!(punycode_encoded_len_str("例子"), Some(7));
+        assert_eq!(punycode_encoded_len(&[0x61, 0xD800]), None);
+        assert_eq!(punycode_encoded_len(&[0x110000]), None);
+        // Overflows the 31-bit delta
+        let mut overflow = vec![0x61; 2100];
+        overflow.push(0x10FFFF);
+        assert_eq!(punycode_encoded_len(&overflow), None);
+        assert!(utf32_to_punycode(&overflow).is_err());
+
+        let arabic = [
+            0x0644, 0x064A, 0x0647, 0x0645, 0x0627, 0x0628, 0x062A, 0x0643, 0x0644, 0x0645, 0x0648,
+            0x0634, 0x0639, 0x0631, 0x0628, 0x064A, 0x061F,
+        ];
+        for input in [&arabic[..], &[0x41, 0xE4, 0x10000, 0xE4, 0x5B50]] {
+            assert_eq!(
+                punycode_encoded_len(input),
+                Some(utf32_to_punycode(input).unwrap().len())
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_bootstring_rejects_invalid_parameters() {
+        Bootstring::new(b"ab", b'-', 1, 2, 38, 700, 72, 128);
+    }
+
     #[test]
     fn test_xn_prefix_rejection() {
         // Should reject input starting with "xn--"
-        assert_eq!(punycode_to_utf32("xn--test"), None);
+        assert_eq!(
+            punycode_to_utf32("xn--test"),
+            Err(PunycodeError::new(AcePrefix, 0))
+        );
         assert!(!verify_punycode("xn--test"));
     }
 }
@@ -0,0 +1,210 @@
+//! Unicode scripts and the Script_Extensions property.
+
+use crate::unicode_tables::{
+    CONFUSABLE_SCRIPTS_CHARS, CONFUSABLE_SCRIPTS_SETS, SCRIPT_CODES, SCRIPT_EXTENSIONS_BLOCK,
+    SCRIPT_EXTENSIONS_INDEX, SCRIPT_NAMES, SCRIPT_SETS,
+};
+use core::fmt;
+
+/// A Unicode script, or one of the ISO 15924 script combinations (`Hanb`,
+/// `Jpan`, `Kore`) used by UTS #39.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Script(u8);
+
+impl Script {
+    pub const ARABIC: Script = Script::by_code("Arab");
+    pub const BOPOMOFO: Script = Script::by_code("Bopo");
+    pub const COMMON: Script = Script::by_code("Zyyy");
+    pub const CYRILLIC: Script = Script::by_code("Cyrl");
+    pub const GREEK: Script = Script::by_code("Grek");
+    pub const HAN: Script = Script::by_code("Hani");
+    pub const HAN_WITH_BOPOMOFO: Script = Script::by_code("Hanb");
+    pub const HANGUL: Script = Script::by_code("Hang");
+    pub const HEBREW: Script = Script::by_code("Hebr");
+    pub const HIRAGANA: Script = Script::by_code("Hira");
+    pub const INHERITED: Script = Script::by_code("Zinh");
+    pub const JAPANESE: Script = Script::by_code("Jpan");
+    pub const KATAKANA: Script = Script::by_code("Kana");
+    pub const KOREAN: Script = Script::by_code("Kore");
+    pub const LATIN: Script = Script::by_code("Latn");
+    pub const UNKNOWN: Script = Script::by_code("Zzzz");
+
+    /// Looks up a script by its four-letter ISO 15924 code, e.g. `"Latn"`.
+    pub fn from_code(code: &str) -> Option<Script> {
+        SCRIPT_CODES
+            .binary_search(&code)
+            .ok()
+            .map(|i| Script(i as u8))
+    }
+
+    /// The four-letter ISO 15924 code, e.g. `"Latn"`.
+    pub fn code(self) -> &'static str {
+        SCRIPT_CODES[self.0 as usize]
+    }
+
+    /// The Unicode property value name, e.g. `"Latin"`.
+    pub fn name(self) -> &'static str {
+        SCRIPT_NAMES[self.0 as usize]
+    }
+
+    const fn by_code(code: &str) -> Script {
+        // Every code is four ASCII letters
+        let code = code.as_bytes();
+        let mut i = 0;
+        while i < SCRIPT_CODES.len() {
+            let candidate = SCRIPT_CODES[i].as_bytes();
+            if candidate[0] == code[0]
+                && candidate[1] == code[1]
+                && candidate[2] == code[2]
+                && candidate[3] == code[3]
+            {
+                return Script(i as u8);
+            }
+            i += 1;
+        }
+        panic!("unknown script code");
+    }
+}
+
+impl fmt::Debug for Script {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+/// A set of [`Script`]s.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct ScriptSet([u64; 3]);
+
+impl ScriptSet {
+    /// The empty set.
+    pub const EMPTY: ScriptSet = ScriptSet([0; 3]);
+
+    /// The set of every script.
+    pub const ALL: ScriptSet = ScriptSet::all();
+
+    const fn all() -> ScriptSet {
+        let mut words = [0; 3];
+        let mut i = 0;
+        while i < SCRIPT_CODES.len() {
+            words[i / 64] |= 1 << (i % 64);
+            i += 1;
+        }
+        ScriptSet(words)
+    }
+
+    pub fn contains(&self, script: Script) -> bool {
+        self.0[script.0 as usize / 64] & (1 << (script.0 % 64)) != 0
+    }
+
+    pub fn insert(&mut self, script: Script) {
+        self.0[script.0 as usize / 64] |= 1 << (script.0 % 64);
+    }
+
+    pub fn remove(&mut self, script: Script) {
+        self.0[script.0 as usize / 64] &= !(1 << (script.0 % 64));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == [0; 3]
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.iter().map(|word| word.count_ones() as usize).sum()
+    }
+
+    pub fn intersection(&self, other: &ScriptSet) -> ScriptSet {
+        ScriptSet([0, 1, 2].map(|i| self.0[i] & other.0[i]))
+    }
+
+    pub fn union(&self, other: &ScriptSet) -> ScriptSet {
+        ScriptSet([0, 1, 2].map(|i| self.0[i] | other.0[i]))
+    }
+
+    /// The scripts in `self` that are not in `other`.
+    pub fn difference(&self, other: &ScriptSet) -> ScriptSet {
+        ScriptSet([0, 1, 2].map(|i| self.0[i] & !other.0[i]))
+    }
+
+    /// The scripts in the set, ordered by code.
+    pub fn iter(&self) -> impl Iterator<Item = Script> + '_ {
+        (0..SCRIPT_CODES.len() as u8)
+            .map(Script)
+            .filter(|&script| self.contains(script))
+    }
+}
+
+impl FromIterator<Script> for ScriptSet {
+    fn from_iter<I: IntoIterator<Item = Script>>(iter: I) -> Self {
+        let mut set = ScriptSet::EMPTY;
+        for script in iter {
+            set.insert(script);
+        }
+        set
+    }
+}
+
+impl fmt::Debug for ScriptSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+/// The Script_Extensions property of `c`: the scripts it is commonly used
+/// with. Characters shared by all scripts have `{Zyyy}` (Common) or `{Zinh}`
+/// (Inherited), and unassigned code points `{Zzzz}`.
+pub fn script_extensions(c: char) -> ScriptSet {
+    let cp = c as usize;
+    let block = SCRIPT_EXTENSIONS_INDEX[cp >> 8] as usize;
+    ScriptSet(SCRIPT_SETS[SCRIPT_EXTENSIONS_BLOCK[block][cp & 0xFF] as usize])
+}
+
+/// The scripts of `c` and of the characters it is confusable with (those
+/// with the same UTS #39 prototype), not counting Common and Inherited ones.
+pub(crate) fn confusable_scripts(c: char) -> ScriptSet {
+    match CONFUSABLE_SCRIPTS_CHARS.binary_search(&(c as u32)) {
+        Ok(i) => ScriptSet(SCRIPT_SETS[CONFUSABLE_SCRIPTS_SETS[i] as usize]),
+        Err(_) => script_extensions(c),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_script_codes() {
+        assert_eq!(Script::from_code("Latn"), Some(Script::LATIN));
+        assert_eq!(Script::from_code("latn"), None);
+        assert_eq!(Script::LATIN.code(), "Latn");
+        assert_eq!(Script::CYRILLIC.name(), "Cyrillic");
+        assert_eq!(Script::JAPANESE.name(), "Japanese");
+        assert_eq!(ScriptSet::ALL.len(), SCRIPT_CODES.len());
+        assert!(ScriptSet::EMPTY.is_empty());
+    }
+
+    #[test]
+    fn test_script_extensions() {
+        let set = |codes: &[&str]| -> ScriptSet {
+            codes
+                .iter()
+                .map(|code| Script::from_code(code).unwrap())
+                .collect()
+        };
+        assert_eq!(script_extensions('a'), set(&["Latn"]));
+        assert_eq!(script_extensions('\u{430}'), set(&["Cyrl"]));
+        assert_eq!(script_extensions('1'), set(&["Zyyy"]));
+        assert_eq!(script_extensions('\u{FE00}'), set(&["Zinh"]));
+        assert_eq!(script_extensions('\u{30FC}'), set(&["Hira", "Kana"]));
+        assert_eq!(script_extensions('\u{660}'), set(&["Arab", "Thaa", "Yezi"]));
+        assert_eq!(script_extensions('\u{378}'), set(&["Zzzz"]));
+        assert_eq!(script_extensions('\u{10FFFF}'), set(&["Zzzz"]));
+        // Unicode 16.0 data: Kirat Rai is assigned, Sidetic (17.0) is not
+        assert_eq!(script_extensions('\u{16D40}'), set(&["Krai"]));
+        assert_eq!(script_extensions('\u{10940}'), set(&["Zzzz"]));
+        assert_eq!(
+            format!("{:?}", script_extensions('\u{30FC}')),
+            "{Hira, Kana}"
+        );
+    }
+}
@@ -0,0 +1,395 @@
+//! Homograph detection based on UTS #39 (Unicode Security Mechanisms).
+//!
+//! The functions here work on Unicode text. To check domain names, pass the
+//! output of [`to_unicode`](crate::to_unicode) so that A-labels are compared
+//! by the characters they display as.
+
+use crate::normalization::{CanonicalDecomposition, is_segment_start, normalize};
+use crate::script::{Script, ScriptSet, script_extensions};
+use crate::unicode_tables::{
+    CONFUSABLE_OFFSETS, CONFUSABLE_SOURCES, CONFUSABLE_TARGETS, IDENTIFIER_ALLOWED,
+};
+use crate::uts46::is_ignored;
+
+/// Recommended scripts (UAX #31, Table 5) besides Latin, Cyrillic and Greek,
+/// which are the ones a moderately restrictive label may add to Latin.
+const MODERATELY_RESTRICTIVE_SCRIPTS: [&str; 26] = [
+    "Arab", "Armn", "Beng", "Bopo", "Deva", "Ethi", "Geor", "Gujr", "Guru", "Hang", "Hani", "Hebr",
+    "Hira", "Kana", "Khmr", "Knda", "Laoo", "Mlym", "Mymr", "Orya", "Sinh", "Taml", "Telu", "Thaa",
+    "Thai", "Tibt",
+];
+
+/// Restriction levels of UTS #39, section 5.2, from most to least
+/// restrictive. Levels are ordered, so `level <= HighlyRestrictive` accepts
+/// the three strictest ones.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RestrictionLevel {
+    /// Only ASCII characters.
+    Ascii,
+    /// Characters of a single script, plus Common and Inherited ones.
+    SingleScript,
+    /// Latin with Han and Hiragana/Katakana, Han and Bopomofo, or Han and
+    /// Hangul.
+    HighlyRestrictive,
+    /// Latin with one other Recommended script except Cyrillic and Greek.
+    ModeratelyRestrictive,
+    /// Any mix of scripts.
+    MinimallyRestrictive,
+    /// Characters outside the General Security Profile (that is, not
+    /// Identifier_Status=Allowed).
+    Unrestricted,
+}
+
+/// The prototype `cp` is visually confusable with, if it has one.
+fn prototype(cp: u32) -> Option<&'static [u32]> {
+    let i = CONFUSABLE_SOURCES.binary_search(&cp).ok()?;
+    let start = CONFUSABLE_OFFSETS[i] as usize;
+    let end = CONFUSABLE_OFFSETS[i + 1] as usize;
+    Some(&CONFUSABLE_TARGETS[start..end])
+}
+
+/// Streams the skeleton of `input`: its NFD form without Default_Ignorable
+/// code points, with every code point replaced by its prototype, decomposed
+/// again.
+fn skeleton_code_points(input: &str) -> impl Iterator<Item = u32> + '_ {
+    let visible = CanonicalDecomposition::new(input.chars())
+        .filter(|&cp| !char::from_u32(cp).is_some_and(is_default_ignorable));
+    let prototypes = visible.flat_map(|cp| {
+        let (mapped, unmapped) = match prototype(cp) {
+            Some(mapped) => (mapped, None),
+            None => (&[][..], Some(cp)),
+        };
+        mapped.iter().copied().chain(unmapped)
+    });
+    CanonicalDecomposition::new(prototypes.filter_map(char::from_u32))
+}
+
+/// Returns the confusable skeleton of `input` (UTS #39, section 4).
+///
+/// Two strings with the same skeleton are likely to look alike, e.g.
+/// `"аpple"` (with a Cyrillic `а`) and `"apple"`. Skeletons are only meant
+/// for comparison and should not be displayed.
+pub fn skeleton(input: &str) -> String {
+    skeleton_code_points(input)
+        .filter_map(char::from_u32)
+        .collect()
+}
+
+/// Returns `true` if `a` and `b` have the same confusable skeleton.
+///
+/// Unlike comparing the results of [`skeleton`], the comparison is done
+/// incrementally and stops at the first difference.
+pub fn are_confusable(a: &str, b: &str) -> bool {
+    skeleton_code_points(a).eq(skeleton_code_points(b))
+}
+
+/// The kind of problem an [`audit`] [`Finding`] reports.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FindingKind {
+    /// Ignored by UTS #46 mapping, so the code point never reaches the
+    /// A-label.
+    Ignored,
+    /// A bidirectional formatting control (Bidi_Control), which can reorder
+    /// the displayed text.
+    BidiControl,
+    /// Invisible in normal rendering (Default_Ignorable_Code_Point).
+    DefaultIgnorable,
+    /// Use is strongly discouraged (Deprecated).
+    Deprecated,
+    /// Part of a sequence that is not in NFC, e.g. `e` followed by U+0301
+    /// instead of `é`.
+    NotNfc,
+}
+
+/// A code point reported by [`audit`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Finding {
+    pub kind: FindingKind,
+    /// Byte offset of the code point in the audited input.
+    pub position: usize,
+    pub code_point: char,
+}
+
+/// Reports the code points of `domain` that are invisible, reorder text,
+/// are deprecated, or disappear or change during conversion.
+///
+/// Findings are ordered by position, and a code point may be reported once
+/// for each [`FindingKind`] that applies. The input is audited as given:
+/// A-labels are not decoded, and nothing about conversion changes.
+pub fn audit(domain: &str) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut segment_is_nfc = true;
+    for (position, c) in domain.char_indices() {
+        if position == 0 || is_segment_start(c) {
+            let end = domain[position + c.len_utf8()..]
+                .char_indices()
+                .find(|&(_, c)| is_segment_start(c))
+                .map_or(domain.len(), |(i, _)| position + c.len_utf8() + i);
+            let segment = &domain[position..end];
+            segment_is_nfc = segment.is_ascii() || normalize(segment) == segment;
+        }
+        for (kind, applies) in [
+            (FindingKind::Ignored, is_ignored(c)),
+            (FindingKind::BidiControl, is_bidi_control(c)),
+            (FindingKind::DefaultIgnorable, is_default_ignorable(c)),
+            (FindingKind::Deprecated, is_deprecated(c)),
+            (FindingKind::NotNfc, !segment_is_nfc),
+        ] {
+            if applies {
+                findings.push(Finding {
+                    kind,
+                    position,
+                    code_point: c,
+                });
+            }
+        }
+    }
+    findings
+}
+
+/// Bidi_Control (PropList.txt).
+fn is_bidi_control(c: char) -> bool {
+    matches!(c, '\u{061C}' | '\u{200E}' | '\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}')
+}
+
+/// Default_Ignorable_Code_Point (DerivedCoreProperties.txt).
+fn is_default_ignorable(c: char) -> bool {
+    matches!(
+        c,
+        '\u{00AD}'
+            | '\u{034F}'
+            | '\u{061C}'
+            | '\u{115F}'..='\u{1160}'
+            | '\u{17B4}'..='\u{17B5}'
+            | '\u{180B}'..='\u{180F}'
+            | '\u{200B}'..='\u{200F}'
+            | '\u{202A}'..='\u{202E}'
+            | '\u{2060}'..='\u{206F}'
+            | '\u{3164}'
+            | '\u{FE00}'..='\u{FE0F}'
+            | '\u{FEFF}'
+            | '\u{FFA0}'
+            | '\u{FFF0}'..='\u{FFF8}'
+            | '\u{1BCA0}'..='\u{1BCA3}'
+            | '\u{1D173}'..='\u{1D17A}'
+            | '\u{E0000}'..='\u{E0FFF}'
+    )
+}
+
+/// Deprecated (PropList.txt).
+fn is_deprecated(c: char) -> bool {
+    matches!(
+        c,
+        '\u{0149}'
+            | '\u{0673}'
+            | '\u{0F77}'
+            | '\u{0F79}'
+            | '\u{17A3}'..='\u{17A4}'
+            | '\u{206A}'..='\u{206F}'
+            | '\u{2329}'..='\u{232A}'
+            | '\u{E0001}'
+    )
+}
+
+/// Returns the restriction level of `label` (UTS #39, section 5.2).
+///
+/// `label` should be a U-label, such as a label of the output of
+/// [`to_unicode`](crate::to_unicode).
+pub fn restriction_level(label: &str) -> RestrictionLevel {
+    if !label.chars().all(is_identifier_allowed) {
+        return RestrictionLevel::Unrestricted;
+    }
+    if label.is_ascii() {
+        return RestrictionLevel::Ascii;
+    }
+    if !resolved_script_set(label.chars()).is_empty() {
+        return RestrictionLevel::SingleScript;
+    }
+    let without_latin = resolved_script_set(
+        label
+            .chars()
+            .filter(|&c| !script_extensions(c).contains(Script::LATIN)),
+    );
+    if [Script::JAPANESE, Script::KOREAN, Script::HAN_WITH_BOPOMOFO]
+        .into_iter()
+        .any(|script| without_latin.contains(script))
+    {
+        return RestrictionLevel::HighlyRestrictive;
+    }
+    let moderate: ScriptSet = MODERATELY_RESTRICTIVE_SCRIPTS
+        .iter()
+        .filter_map(|code| Script::from_code(code))
+        .collect();
+    if !without_latin.intersection(&moderate).is_empty() {
+        return RestrictionLevel::ModeratelyRestrictive;
+    }
+    RestrictionLevel::MinimallyRestrictive
+}
+
+/// `true` for characters with Identifier_Status=Allowed.
+fn is_identifier_allowed(c: char) -> bool {
+    let cp = c as u32;
+    IDENTIFIER_ALLOWED
+        .binary_search_by(|&(lo, hi)| {
+            if hi < cp {
+                core::cmp::Ordering::Less
+            } else if lo > cp {
+                core::cmp::Ordering::Greater
+            } else {
+                core::cmp::Ordering::Equal
+            }
+        })
+        .is_ok()
+}
+
+/// The augmented script set of `c` (UTS #39, section 5.1): its
+/// Script_Extensions plus the writing systems that combine its scripts, or
+/// every script for Common and Inherited characters.
+fn augmented_script_set(c: char) -> ScriptSet {
+    let mut set = script_extensions(c);
+    if set.contains(Script::COMMON) || set.contains(Script::INHERITED) {
+        return ScriptSet::ALL;
+    }
+    let han = set.contains(Script::HAN);
+    if han || set.contains(Script::HIRAGANA) || set.contains(Script::KATAKANA) {
+        set.insert(Script::JAPANESE);
+    }
+    if han || set.contains(Script::HANGUL) {
+        set.insert(Script::KOREAN);
+    }
+    if han || set.contains(Script::BOPOMOFO) {
+        set.insert(Script::HAN_WITH_BOPOMOFO);
+    }
+    set
+}
+
+/// The resolved script set of a string: the scripts shared by every
+/// character. An empty set means the string is mixed-script.
+fn resolved_script_set(chars: impl Iterator<Item = char>) -> ScriptSet {
+    chars.fold(ScriptSet::ALL, |set, c| {
+        set.intersection(&augmented_script_set(c))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_skeleton() {
+        assert_eq!(skeleton("apple.org"), "apple.org");
+        assert_eq!(skeleton("\u{430}pple.org"), "apple.org");
+        // Prototypes can be longer than the characters they replace
+        assert_eq!(skeleton("microsoft"), "rnicrosoft");
+        assert_eq!(skeleton("examp1e"), "exarnple");
+        assert_eq!(skeleton(""), "");
+        assert_eq!(skeleton("a\u{200B}\u{FE0F}pple"), "apple");
+    }
+
+    #[test]
+    fn test_are_confusable() {
+        assert!(are_confusable("\u{430}pple.com", "apple.com"));
+        assert!(are_confusable("p\u{430}yp\u{430}l", "paypal"));
+        assert!(are_confusable("rnicrosoft", "microsoft"));
+        assert!(are_confusable("caf\u{e9}", "cafe\u{301}"));
+        assert!(!are_confusable("apple.com", "apples.com"));
+        assert!(!are_confusable("apple.com", "apple.co"));
+        // Default_Ignorable code points are removed
+        assert!(are_confusable("pay\u{200B}pal", "paypal"));
+        assert!(are_confusable("p\u{430}y\u{200D}p\u{430}l", "paypal"));
+        assert!(are_confusable("pay\u{AD}pal", "paypal"));
+    }
+
+    #[test]
+    fn test_are_confusable_u_labels() {
+        let ascii = crate::to_ascii("\u{430}pple.com").unwrap();
+        assert!(ascii.starts_with("xn--"));
+        assert!(!are_confusable(&ascii, "apple.com"));
+        let unicode = crate::to_unicode(&ascii).unwrap();
+        assert!(are_confusable(&unicode, "apple.com"));
+    }
+
+    #[test]
+    fn test_restriction_level() {
+        use RestrictionLevel::*;
+        assert_eq!(restriction_level("example"), Ascii);
+        assert_eq!(restriction_level("caf\u{e9}"), SingleScript);
+        assert_eq!(
+            restriction_level("\u{43f}\u{440}\u{438}\u{43c}\u{435}\u{440}"),
+            SingleScript
+        );
+        assert_eq!(restriction_level("\u{65e5}\u{672c}\u{3054}"), SingleScript);
+        assert_eq!(
+            restriction_level("abc\u{65e5}\u{672c}\u{3054}"),
+            HighlyRestrictive
+        );
+        assert_eq!(restriction_level("abc\u{d55c}\u{5b57}"), HighlyRestrictive);
+        assert_eq!(
+            restriction_level("abc\u{905}\u{915}"),
+            ModeratelyRestrictive
+        );
+        assert_eq!(restriction_level("\u{430}pple"), MinimallyRestrictive);
+        assert_eq!(restriction_level("abc\u{3b1}\u{3b2}"), MinimallyRestrictive);
+        assert_eq!(restriction_level("\u{43f}\u{65e5}"), MinimallyRestrictive);
+        assert_eq!(restriction_level("a!b"), Unrestricted);
+        assert_eq!(restriction_level("a\u{2665}"), Unrestricted);
+        assert!(restriction_level("abc\u{65e5}") <= HighlyRestrictive);
+        assert!(restriction_level("\u{430}pple") > HighlyRestrictive);
+    }
+
+    #[test]
+    fn test_audit() {
+        use FindingKind::*;
+        let audited = |input: &str| -> Vec<(FindingKind, usize)> {
+            audit(input)
+                .into_iter()
+                .map(|finding| (finding.kind, finding.position))
+                .collect()
+        };
+        assert_eq!(audited("example.com"), []);
+        assert_eq!(audited("b\u{fc}cher.example"), []);
+        assert_eq!(
+            audited("ex\u{AD}ample.com"),
+            [(Ignored, 2), (DefaultIgnorable, 2)]
+        );
+        // UTS #46 disallows bidi overrides rather than ignoring them
+        assert_eq!(
+            audited("a\u{202E}moc"),
+            [(BidiControl, 1), (DefaultIgnorable, 1)]
+        );
+        assert_eq!(
+            audited("a\u{2066}b"),
+            [(BidiControl, 1), (DefaultIgnorable, 1)]
+        );
+        assert_eq!(audited("a\u{200B}b"), [(Ignored, 1), (DefaultIgnorable, 1)]);
+        assert_eq!(
+            audited("a\u{2060}\u{FE0F}b"),
+            [
+                (Ignored, 1),
+                (DefaultIgnorable, 1),
+                (Ignored, 4),
+                (DefaultIgnorable, 4)
+            ]
+        );
+        // Joiners are kept by nontransitional processing
+        assert_eq!(audited("a\u{200D}b"), [(DefaultIgnorable, 1)]);
+        assert_eq!(audited("\u{149}"), [(Deprecated, 0)]);
+        assert_eq!(audited("cafe\u{301}"), [(NotNfc, 3), (NotNfc, 4)]);
+        assert_eq!(audited("\u{212B}x"), [(NotNfc, 0)]);
+        assert_eq!(audited("x\u{1100}\u{1161}"), [(NotNfc, 1), (NotNfc, 4)]);
+        assert_eq!(audited("\u{DD9}\u{DCF}"), [(NotNfc, 0), (NotNfc, 3)]);
+        assert_eq!(audited("caf\u{e9}\u{301}"), []);
+        let finding = audit("\u{200D}")[0];
+        assert_eq!(finding.code_point, '\u{200D}');
+
+        // Auditing leaves conversion alone
+        let input = "ex\u{AD}am\u{200C}ple.com";
+        assert_eq!(crate::map(input), "example.com");
+        // Ignored code points are the ones UTS #46 processing drops
+        let options = crate::Uts46Options::default();
+        assert_eq!(
+            crate::to_ascii_with_options("ex\u{200B}am\u{FE0F}ple.com", &options).as_deref(),
+            Ok("example.com")
+        );
+    }
+}
@@ -1,94 +1,186 @@
-pub fn utf8_to_utf32(buf: &[u8]) -> Vec<u32> {
+use crate::ascii;
+
+/// Malformed UTF-8 found by [`utf8_to_utf32`].
+///
+/// Mirrors [`std::str::Utf8Error`]: `valid_up_to` is the length of the valid
+/// prefix, and `error_len` is the length of the invalid sequence starting
+/// there, or `None` if the input ends in the middle of a sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Utf8Error {
+    pub valid_up_to: usize,
+    pub error_len: Option<u8>,
+}
+
+/// Decodes UTF-8 into code points, failing on the first malformed sequence.
+pub fn utf8_to_utf32(buf: &[u8]) -> Result<Vec<u32>, Utf8Error> {
+    // Validating while counting sizes the output exactly; decoding then
+    // cannot meet an error.
+    let mut output = Vec::with_capacity(utf32_length_from_utf8_checked(buf)?);
+    decode_utf8_lossy_into(buf, &mut output);
+    Ok(output)
+}
+
+/// Decodes UTF-8 into code points, replacing every maximal subpart of an
+/// ill-formed sequence with U+FFFD as the WHATWG Encoding Standard and
+/// Unicode §3.9 recommend.
+pub fn utf8_to_utf32_lossy(buf: &[u8]) -> Vec<u32> {
+    let mut output = Vec::with_capacity(utf8_to_utf32_length(buf));
+    decode_utf8_lossy_into(buf, &mut output);
+    output
+}
+
+fn decode_utf8_lossy_into(buf: &[u8], output: &mut Vec<u32>) {
     let mut pos = 0;
     let len = buf.len();
-    let mut output = Vec::with_capacity(utf8_to_utf32_length(buf)); // Use exact capacity for better performance
 
     while pos < len {
-        // Optimized ASCII fast path similar to C++ implementation
-        if pos + 16 <= len {
-            let chunk = unsafe { std::slice::from_raw_parts(buf.as_ptr().add(pos), 16) };
-
-            // Check if all 16 bytes are ASCII using efficient OR operation
-            let mut ascii_check = 0u8;
-            for &byte in chunk {
-                ascii_check |= byte;
-            }
-
-            if ascii_check < 0x80 {
-                // All ASCII - efficient bulk copy
-                output.extend(chunk.iter().map(|&b| b as u32));
-                pos += 16;
-                continue;
-            }
+        // Vectorized ASCII fast path
+        if buf[pos] < 0x80 {
+            let ascii = ascii::ascii_prefix_len(&buf[pos..]);
+            output.extend(buf[pos..pos + ascii].iter().map(|&b| b as u32));
+            pos += ascii;
+            continue;
         }
 
-        let leading_byte = buf[pos];
-        if leading_byte < 0x80 {
-            output.push(leading_byte as u32);
-            pos += 1;
-        } else if (leading_byte & 0xE0) == 0xC0 {
-            if pos + 1 >= len {
-                return vec![];
-            }
-            if (buf[pos + 1] & 0xC0) != 0x80 {
-                return vec![];
-            }
-            let code_point = (((leading_byte & 0x1F) as u32) << 6) | ((buf[pos + 1] & 0x3F) as u32);
-            if !(0x80..=0x7FF).contains(&code_point) {
-                return vec![];
-            }
-            output.push(code_point);
-            pos += 2;
-        } else if (leading_byte & 0xF0) == 0xE0 {
-            if pos + 2 >= len {
-                return vec![];
+        match decode_code_point(&buf[pos..]) {
+            Ok((code_point, size)) => {
+                output.push(code_point);
+                pos += size;
             }
-            if (buf[pos + 1] & 0xC0) != 0x80 {
-                return vec![];
+            Err(error_len) => {
+                output.push(0xFFFD);
+                match error_len {
+                    Some(size) => pos += size as usize,
+                    // The rest of the input is a single truncated sequence
+                    None => break,
+                }
             }
-            if (buf[pos + 2] & 0xC0) != 0x80 {
-                return vec![];
-            }
-            let code_point = (((leading_byte & 0x0F) as u32) << 12)
-                | (((buf[pos + 1] & 0x3F) as u32) << 6)
-                | ((buf[pos + 2] & 0x3F) as u32);
-            if !(0x800..=0xFFFF).contains(&code_point)
-                || (code_point > 0xD7FF && code_point < 0xE000)
-            {
-                return vec![];
-            }
-            output.push(code_point);
-            pos += 3;
-        } else if (leading_byte & 0xF8) == 0xF0 {
-            if pos + 3 >= len {
-                return vec![];
-            }
-            if (buf[pos + 1] & 0xC0) != 0x80 {
-                return vec![];
-            }
-            if (buf[pos + 2] & 0xC0) != 0x80 {
-                return vec![];
-            }
-            if (buf[pos + 3] & 0xC0) != 0x80 {
-                return vec![];
+        }
+    }
+}
+
+/// Iterator over the code points of UTF-8 bytes, without collecting them.
+///
+/// Yields an error for the first malformed sequence and then stops, like
+/// [`utf8_to_utf32`].
+#[derive(Debug, Clone)]
+pub struct Utf8CodePoints<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Utf8CodePoints<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+}
+
+impl Iterator for Utf8CodePoints<'_> {
+    type Item = Result<char, Utf8Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos >= self.bytes.len() {
+            return None;
+        }
+        match decode_code_point(&self.bytes[self.pos..]) {
+            Ok((code_point, size)) => {
+                self.pos += size;
+                // Table 3-7 excludes surrogates and values above U+10FFFF
+                char::from_u32(code_point).map(Ok)
             }
-            let code_point = (((leading_byte & 0x07) as u32) << 18)
-                | (((buf[pos + 1] & 0x3F) as u32) << 12)
-                | (((buf[pos + 2] & 0x3F) as u32) << 6)
-                | ((buf[pos + 3] & 0x3F) as u32);
-            if code_point <= 0xFFFF || code_point > 0x10FFFF {
-                return vec![];
+            Err(error_len) => {
+                let error = Utf8Error {
+                    valid_up_to: self.pos,
+                    error_len,
+                };
+                self.pos = self.bytes.len();
+                Some(Err(error))
             }
-            output.push(code_point);
-            pos += 4;
-        } else {
-            return vec![];
         }
     }
+}
 
-    output
+/// Iterator over the UTF-8 bytes of a sequence of `char`s, without
+/// collecting them.
+#[derive(Debug, Clone)]
+pub struct EncodeUtf8<I> {
+    iter: I,
+    buf: [u8; 4],
+    pos: u8,
+    len: u8,
+}
+
+impl<I: Iterator<Item = char>> EncodeUtf8<I> {
+    pub fn new<T: IntoIterator<IntoIter = I>>(iter: T) -> Self {
+        Self {
+            iter: iter.into_iter(),
+            buf: [0; 4],
+            pos: 0,
+            len: 0,
+        }
+    }
+}
+
+impl<I: Iterator<Item = char>> Iterator for EncodeUtf8<I> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        if self.pos == self.len {
+            let c = self.iter.next()?;
+            self.len = c.encode_utf8(&mut self.buf).len() as u8;
+            self.pos = 0;
+        }
+        let byte = self.buf[self.pos as usize];
+        self.pos += 1;
+        Some(byte)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let pending = (self.len - self.pos) as usize;
+        let (lower, upper) = self.iter.size_hint();
+        (
+            pending + lower,
+            upper.and_then(|upper| upper.checked_mul(4)?.checked_add(pending)),
+        )
+    }
 }
 
+/// Decodes the code point at the start of the non-empty `buf`, returning it
+/// with its length in bytes.
+///
+/// On failure returns the length of the maximal subpart of the ill-formed
+/// sequence (Unicode Table 3-7), or `None` if `buf` ends inside it.
+fn decode_code_point(buf: &[u8]) -> Result<(u32, usize), Option<u8>> {
+    let leading_byte = buf[0];
+    // Sequence length and the range allowed for the second byte
+    let (size, second) = match leading_byte {
+        0x00..=0x7F => return Ok((leading_byte as u32, 1)),
+        0xC2..=0xDF => (2, 0x80..=0xBF),
+        0xE0 => (3, 0xA0..=0xBF),
+        0xE1..=0xEC | 0xEE..=0xEF => (3, 0x80..=0xBF),
+        0xED => (3, 0x80..=0x9F),
+        0xF0 => (4, 0x90..=0xBF),
+        0xF1..=0xF3 => (4, 0x80..=0xBF),
+        0xF4 => (4, 0x80..=0x8F),
+        _ => return Err(Some(1)),
+    };
+
+    let mut code_point = (leading_byte & (0x7F >> size)) as u32;
+    for i in 1..size {
+        let Some(&byte) = buf.get(i) else {
+            return Err(None);
+        };
+        let allowed = if i == 1 { second.clone() } else { 0x80..=0xBF };
+        if !allowed.contains(&byte) {
+            return Err(Some(i as u8));
+        }
+        code_point = (code_point << 6) | (byte & 0x3F) as u32;
+    }
+    Ok((code_point, size))
+}
+
+/// Number of UTF-8 bytes needed for `buf`, assuming every element is a
+/// Unicode scalar value. See [`utf8_length_from_utf32_checked`].
 pub fn utf8_length_from_utf32(buf: &[u32]) -> usize {
     let mut counter = 0;
     for &cp in buf {
@@ -106,36 +198,90 @@ pub fn utf8_length_from_utf32(buf: &[u32]) -> usize {
     counter
 }
 
+/// Number of code points in `buf`, assuming it is valid UTF-8. See
+/// [`utf32_length_from_utf8_checked`].
 pub fn utf32_length_from_utf8(buf: &[u8]) -> usize {
     buf.iter().filter(|&&b| (b as i8) > -65).count()
 }
 
+/// Number of code points in `buf`, or the first malformed sequence if it is
+/// not valid UTF-8.
+pub fn utf32_length_from_utf8_checked(buf: &[u8]) -> Result<usize, Utf8Error> {
+    let mut pos = 0;
+    let mut count = 0;
+    while pos < buf.len() {
+        let ascii = ascii::ascii_prefix_len(&buf[pos..]);
+        pos += ascii;
+        count += ascii;
+        if pos == buf.len() {
+            break;
+        }
+        match decode_code_point(&buf[pos..]) {
+            Ok((_, size)) => {
+                pos += size;
+                count += 1;
+            }
+            Err(error_len) => {
+                return Err(Utf8Error {
+                    valid_up_to: pos,
+                    error_len,
+                });
+            }
+        }
+    }
+    Ok(count)
+}
+
+/// Number of UTF-8 bytes needed for `buf`, or `None` if it contains a
+/// surrogate or a value above U+10FFFF.
+pub fn utf8_length_from_utf32_checked(buf: &[u32]) -> Option<usize> {
+    let mut counter = 0;
+    for &cp in buf {
+        counter += match cp {
+            0..=0x7F => 1,
+            0x80..=0x7FF => 2,
+            0xD800..=0xDFFF => return None,
+            0x800..=0xFFFF => 3,
+            0x10000..=0x10FFFF => 4,
+            _ => return None,
+        };
+    }
+    Some(counter)
+}
+
 pub fn utf8_to_utf32_length(buf: &[u8]) -> usize {
     utf32_length_from_utf8(buf)
 }
 
-pub fn utf32_to_utf8(buf: &[u32]) -> Vec<u8> {
+/// A surrogate or a value above U+10FFFF found by [`utf32_to_utf8`] at index
+/// `valid_up_to`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Utf32Error {
+    pub valid_up_to: usize,
+}
+
+/// Encodes code points as UTF-8, failing on the first one that is not a
+/// Unicode scalar value.
+pub fn utf32_to_utf8(buf: &[u32]) -> Result<Vec<u8>, Utf32Error> {
     let mut pos = 0;
     let len = buf.len();
-    let mut output = Vec::with_capacity(utf8_length_from_utf32(buf)); // Use exact capacity for better performance
+    // Validating while counting sizes the output exactly
+    let Some(utf8_len) = utf8_length_from_utf32_checked(buf) else {
+        let valid_up_to = buf
+            .iter()
+            .take_while(|&&cp| char::from_u32(cp).is_some())
+            .count();
+        return Err(Utf32Error { valid_up_to });
+    };
+    let mut output = Vec::with_capacity(utf8_len);
 
     while pos < len {
         // ASCII fast path for multiple codepoints
-        if pos + 4 <= len {
-            let chunk = unsafe { std::slice::from_raw_parts(buf.as_ptr().add(pos), 4) };
-
-            // Check if all 4 codepoints are ASCII
-            let mut ascii_check = 0u32;
-            for &cp in chunk {
-                ascii_check |= cp;
-            }
-
-            if ascii_check < 0x80 {
-                // All ASCII - bulk convert
-                output.extend(chunk.iter().map(|&cp| cp as u8));
-                pos += 4;
-                continue;
-            }
+        if buf[pos] < 0x80 {
+            let ascii = ascii::ascii_prefix_len_utf32(&buf[pos..]);
+            output.extend(buf[pos..pos + ascii].iter().map(|&cp| cp as u8));
+            pos += ascii;
+            continue;
         }
 
         let word = buf[pos];
@@ -147,17 +293,11 @@ pub fn utf32_to_utf8(buf: &[u32]) -> Vec<u8> {
             output.push(((word & 0x3F) | 0x80) as u8);
             pos += 1;
         } else if (word & 0xFFFF0000) == 0 {
-            if (0xD800..=0xDFFF).contains(&word) {
-                return vec![];
-            }
             output.push(((word >> 12) | 0xE0) as u8);
             output.push((((word >> 6) & 0x3F) | 0x80) as u8);
             output.push(((word & 0x3F) | 0x80) as u8);
             pos += 1;
         } else {
-            if word > 0x10FFFF {
-                return vec![];
-            }
             output.push(((word >> 18) | 0xF0) as u8);
             output.push((((word >> 12) & 0x3F) | 0x80) as u8);
             output.push((((word >> 6) & 0x3F) | 0x80) as u8);
@@ -166,7 +306,73 @@ pub fn utf32_to_utf8(buf: &[u32]) -> Vec<u8> {
         }
     }
 
-    output
+    Ok(output)
+}
+
+/// A lone surrogate found by [`utf16_to_utf32`] at index `valid_up_to`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Utf16Error {
+    pub valid_up_to: usize,
+}
+
+/// Decodes UTF-16 into code points, failing on the first unpaired surrogate.
+pub fn utf16_to_utf32(buf: &[u16]) -> Result<Vec<u32>, Utf16Error> {
+    transcode_utf16(buf, false)
+}
+
+/// Decodes UTF-16 into code points, replacing every unpaired surrogate with
+/// U+FFFD.
+pub fn utf16_to_utf32_lossy(buf: &[u16]) -> Vec<u32> {
+    match transcode_utf16(buf, true) {
+        Ok(output) => output,
+        Err(_) => unreachable!("lossy decoding never fails"),
+    }
+}
+
+fn transcode_utf16(buf: &[u16], lossy: bool) -> Result<Vec<u32>, Utf16Error> {
+    let mut pos = 0;
+    let len = buf.len();
+    let mut output = Vec::with_capacity(len);
+
+    while pos < len {
+        let word = buf[pos] as u32;
+        if !(0xD800..=0xDFFF).contains(&word) {
+            output.push(word);
+            pos += 1;
+            continue;
+        }
+        let low = buf.get(pos + 1).map_or(0, |&w| w as u32);
+        if word <= 0xDBFF && (0xDC00..=0xDFFF).contains(&low) {
+            output.push(0x10000 + ((word - 0xD800) << 10) + (low - 0xDC00));
+            pos += 2;
+        } else if lossy {
+            output.push(0xFFFD);
+            pos += 1;
+        } else {
+            return Err(Utf16Error { valid_up_to: pos });
+        }
+    }
+
+    Ok(output)
+}
+
+/// Encodes code points as UTF-16, or returns `None` if one of them is a
+/// surrogate or above U+10FFFF.
+pub fn utf32_to_utf16(buf: &[u32]) -> Option<Vec<u16>> {
+    let mut output = Vec::with_capacity(buf.len());
+    for &word in buf {
+        if (0xD800..=0xDFFF).contains(&word) || word > 0x10FFFF {
+            return None;
+        }
+        if word <= 0xFFFF {
+            output.push(word as u16);
+        } else {
+            let word = word - 0x10000;
+            output.push((0xD800 | (word >> 10)) as u16);
+            output.push((0xDC00 | (word & 0x3FF)) as u16);
+        }
+    }
+    Some(output)
 }
 
 #[cfg(test)]
@@ -177,20 +383,112 @@ mod tests {
     fn test_utf8_to_utf32_ascii() {
         let input = b"hello";
         let result = utf8_to_utf32(input);
-        assert_eq!(result, vec![104, 101, 108, 108, 111]);
+        assert_eq!(result, Ok(vec![104, 101, 108, 108, 111]));
     }
 
     #[test]
     fn test_utf8_to_utf32_unicode() {
         let input = "café".as_bytes();
         let result = utf8_to_utf32(input);
-        assert_eq!(result, vec![99, 97, 102, 233]);
+        assert_eq!(result, Ok(vec![99, 97, 102, 233]));
+    }
+
+    #[test]
+    fn test_utf8_to_utf32_errors() {
+        let error = |valid_up_to, error_len| {
+            Err(Utf8Error {
+                valid_up_to,
+                error_len,
+            })
+        };
+        assert_eq!(utf8_to_utf32(b""), Ok(vec![]));
+        assert_eq!(utf8_to_utf32(b"ab\x80"), error(2, Some(1)));
+        assert_eq!(utf8_to_utf32(b"a\xC0\xAF"), error(1, Some(1)));
+        assert_eq!(utf8_to_utf32(b"a\xE0\x80\x80"), error(1, Some(1)));
+        assert_eq!(utf8_to_utf32(b"\xED\xA0\x80"), error(0, Some(1)));
+        assert_eq!(utf8_to_utf32(b"\xF4\x90\x80\x80"), error(0, Some(1)));
+        assert_eq!(utf8_to_utf32(b"\xE2\x82a"), error(0, Some(2)));
+        assert_eq!(utf8_to_utf32(b"\xF0\x9F\x98"), error(0, None));
+        // Past the ASCII fast path
+        let mut long = b"0123456789abcdef0123".to_vec();
+        long.push(0xFF);
+        assert_eq!(utf8_to_utf32(&long), error(20, Some(1)));
+    }
+
+    #[test]
+    fn test_utf8_to_utf32_lossy() {
+        // Examples from Unicode §3.9, "U+FFFD Substitution of Maximal Subparts"
+        assert_eq!(
+            utf8_to_utf32_lossy(b"\x61\xF1\x80\x80\xE1\x80\xC2\x62\x80\x63\x80\xBF\x64"),
+            vec![
+                0x61, 0xFFFD, 0xFFFD, 0xFFFD, 0x62, 0xFFFD, 0x63, 0xFFFD, 0xFFFD, 0x64
+            ]
+        );
+        assert_eq!(
+            utf8_to_utf32_lossy(b"\xC0\xAF\xE0\x80\xBF\xF0\x81\x82\x41"),
+            vec![0xFFFD; 8]
+                .into_iter()
+                .chain([0x41])
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            utf8_to_utf32_lossy(b"\xED\xA0\x80\xED\xBF\xBF\xED\xAF\x41"),
+            vec![0xFFFD; 8]
+                .into_iter()
+                .chain([0x41])
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            utf8_to_utf32_lossy(b"\xF4\x91\x92\x93\xFF\x41\x80\xBF\x42"),
+            vec![
+                0xFFFD, 0xFFFD, 0xFFFD, 0xFFFD, 0xFFFD, 0x41, 0xFFFD, 0xFFFD, 0x42
+            ]
+        );
+        assert_eq!(
+            utf8_to_utf32_lossy(b"\xE1\x80\xE2\xF0\x91\x92\xF1\xBF\x41"),
+            vec![0xFFFD, 0xFFFD, 0xFFFD, 0xFFFD, 0x41]
+        );
+        assert_eq!(
+            utf8_to_utf32_lossy(b"ok\xF0\x9F\x98"),
+            vec![0x6F, 0x6B, 0xFFFD]
+        );
+
+        // Agrees with the standard library on arbitrary bytes
+        let mut state = 0x9E37_79B9_u32;
+        for _ in 0..2000 {
+            let bytes: Vec<u8> = (0..12)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 17;
+                    state ^= state << 5;
+                    [0x41, 0x80, 0xBF, 0xC2, 0xE0, 0xED, 0xF0, 0xF4, 0xFF][state as usize % 9]
+                })
+                .collect();
+            let expected: Vec<u32> = String::from_utf8_lossy(&bytes)
+                .chars()
+                .map(u32::from)
+                .collect();
+            assert_eq!(utf8_to_utf32_lossy(&bytes), expected, "{bytes:x?}");
+            match std::str::from_utf8(&bytes) {
+                Ok(s) => assert_eq!(
+                    utf8_to_utf32(&bytes),
+                    Ok(s.chars().map(u32::from).collect())
+                ),
+                Err(e) => assert_eq!(
+                    utf8_to_utf32(&bytes),
+                    Err(Utf8Error {
+                        valid_up_to: e.valid_up_to(),
+                        error_len: e.error_len().map(|len| len as u8),
+                    })
+                ),
+            }
+        }
     }
 
     #[test]
     fn test_utf32_to_utf8() {
         let input = vec![99, 97, 102, 233];
-        let result = utf32_to_utf8(&input);
+        let result = utf32_to_utf8(&input).unwrap();
         let expected = "café".as_bytes();
         assert_eq!(result, expected);
     }
@@ -202,10 +500,102 @@ mod tests {
         assert_eq!(result, 5); // c(1) + a(1) + f(1) + é(2) = 5 bytes
     }
 
+    #[test]
+    fn test_checked_lengths() {
+        assert_eq!(utf32_length_from_utf8_checked(b""), Ok(0));
+        assert_eq!(utf32_length_from_utf8_checked("café例😀".as_bytes()), Ok(6));
+        let long = "0123456789abcdef0123456789abcdefé";
+        assert_eq!(utf32_length_from_utf8_checked(long.as_bytes()), Ok(33));
+        // The unchecked count is wrong for malformed input
+        assert_eq!(utf32_length_from_utf8(b"\xC3\x28"), 2);
+        assert_eq!(
+            utf32_length_from_utf8_checked(b"\xC3\x28"),
+            Err(Utf8Error {
+                valid_up_to: 0,
+                error_len: Some(1)
+            })
+        );
+        assert_eq!(
+            utf32_length_from_utf8_checked(b"ok\xF0\x9F"),
+            Err(Utf8Error {
+                valid_up_to: 2,
+                error_len: None
+            })
+        );
+
+        assert_eq!(utf8_length_from_utf32_checked(&[]), Some(0));
+        assert_eq!(
+            utf8_length_from_utf32_checked(&[0x7F, 0x80, 0x7FF, 0x800, 0xFFFF, 0x10000, 0x10FFFF]),
+            Some(1 + 2 + 2 + 3 + 3 + 4 + 4)
+        );
+        assert_eq!(utf8_length_from_utf32(&[0xD800]), 3);
+        assert_eq!(utf8_length_from_utf32_checked(&[0x61, 0xD800]), None);
+        assert_eq!(utf8_length_from_utf32_checked(&[0x110000]), None);
+        assert_eq!(
+            utf32_to_utf8(&[0x61, 0xDFFF]),
+            Err(Utf32Error { valid_up_to: 1 })
+        );
+        assert_eq!(
+            utf32_to_utf8(&[0x61, 0xE9, 0x110000, 0xD800]),
+            Err(Utf32Error { valid_up_to: 2 })
+        );
+        assert_eq!(utf32_to_utf8(&[]), Ok(Vec::new()));
+    }
+
     #[test]
     fn test_utf32_length_from_utf8() {
         let input = "café".as_bytes();
         let result = utf32_length_from_utf8(input);
         assert_eq!(result, 4);
     }
+
+    #[test]
+    fn test_utf16_transcoding() {
+        let text = "a\u{E9}\u{4F8B}\u{1F600}";
+        let utf16: Vec<u16> = text.encode_utf16().collect();
+        let utf32 = vec![0x61, 0xE9, 0x4F8B, 0x1F600];
+        assert_eq!(utf16_to_utf32(&utf16), Ok(utf32.clone()));
+        assert_eq!(utf32_to_utf16(&utf32), Some(utf16));
+
+        // Lone high surrogate at the end, lone low surrogate, swapped pair
+        assert_eq!(
+            utf16_to_utf32(&[0x61, 0xD83D]),
+            Err(Utf16Error { valid_up_to: 1 })
+        );
+        assert_eq!(
+            utf16_to_utf32(&[0xDE00, 0x61]),
+            Err(Utf16Error { valid_up_to: 0 })
+        );
+        assert_eq!(
+            utf16_to_utf32_lossy(&[0xDE00, 0xD83D, 0x61, 0xD83D]),
+            vec![0xFFFD, 0xFFFD, 0x61, 0xFFFD]
+        );
+
+        assert_eq!(utf32_to_utf16(&[0xD800]), None);
+        assert_eq!(utf32_to_utf16(&[0x110000]), None);
+    }
+
+    #[test]
+    fn test_transcoding_iterators() {
+        let text = "a\u{E9}\u{4F8B}\u{1F600}";
+        assert!(
+            Utf8CodePoints::new(text.as_bytes())
+                .map(Result::unwrap)
+                .eq(text.chars())
+        );
+        assert!(EncodeUtf8::new(text.chars()).eq(text.bytes()));
+        assert_eq!(EncodeUtf8::new(text.chars()).count(), text.len());
+
+        let mut invalid = Utf8CodePoints::new(b"ab\xE2\x82cd");
+        assert_eq!(invalid.next(), Some(Ok('a')));
+        assert_eq!(invalid.next(), Some(Ok('b')));
+        assert_eq!(
+            invalid.next(),
+            Some(Err(Utf8Error {
+                valid_up_to: 2,
+                error_len: Some(2)
+            }))
+        );
+        assert_eq!(invalid.next(), None);
+    }
 }
@@ -0,0 +1,546 @@
+//! UTS #46 processing with the flags of its ToASCII and ToUnicode operations.
+//!
+//! Unlike [`to_ascii`](crate::to_ascii), which applies the crate's own fast
+//! mapping, this follows the Processing and Validity Criteria sections of
+//! UTS #46 (version 16.0.0) step by step, driven by the IDNA mapping table.
+//! Validation includes the CONTEXTJ rules of RFC 5892 and the Bidi rule of
+//! RFC 5893.
+
+use crate::normalization::{get_ccc, normalize};
+use crate::punycode::PUNYCODE;
+use crate::unicode_tables::{
+    BIDI_CLASSES, JOINING_TYPES, MARKS, UTS46_MAPPING, UTS46_MAPPING_OFFSETS, UTS46_MAPPING_TARGETS,
+};
+
+/// The flags of UTS #46, section 4.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Uts46Options {
+    pub check_hyphens: bool,
+    pub check_bidi: bool,
+    pub check_joiners: bool,
+    pub use_std3_ascii_rules: bool,
+    pub transitional_processing: bool,
+    pub verify_dns_length: bool,
+    pub ignore_invalid_punycode: bool,
+    /// Without CheckHyphens, reject labels that begin with `xn--` after
+    /// conversion, as UTS #46 does since version 15.1.0.
+    pub reject_xn_prefix: bool,
+}
+
+impl Default for Uts46Options {
+    /// Every check enabled, with nontransitional processing.
+    fn default() -> Self {
+        Uts46Options {
+            check_hyphens: true,
+            check_bidi: true,
+            check_joiners: true,
+            use_std3_ascii_rules: true,
+            transitional_processing: false,
+            verify_dns_length: true,
+            ignore_invalid_punycode: false,
+            reject_xn_prefix: true,
+        }
+    }
+}
+
+/// The first error recorded while processing a domain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Uts46Error {
+    /// A code point whose status is not valid, or an ASCII code point other
+    /// than a letter, digit or hyphen with UseSTD3ASCIIRules.
+    DisallowedCodePoint,
+    /// An A-label that is not ASCII, fails to decode, or decodes to an empty
+    /// or all-ASCII label.
+    Punycode,
+    NotNfc,
+    /// A hyphen in the third and fourth or first or last positions with
+    /// CheckHyphens, or an `xn--` prefix without it.
+    Hyphens,
+    LeadingCombiningMark,
+    ContextJ,
+    Bidi,
+    DnsLength,
+}
+
+/// UTS #46 ToASCII.
+pub fn to_ascii(domain: &str, options: &Uts46Options) -> Result<String, Uts46Error> {
+    let (labels, error) = process(domain, options);
+    if let Some(error) = error {
+        return Err(error);
+    }
+
+    let result = encode_labels(&labels)?;
+    if options.verify_dns_length && !verify_dns_length(&result) {
+        return Err(Uts46Error::DnsLength);
+    }
+    Ok(result)
+}
+
+/// The VerifyDnsLength check of an ASCII domain. The root label and its dot
+/// are not counted.
+pub(crate) fn verify_dns_length(domain: &str) -> bool {
+    let domain = domain.strip_suffix('.').unwrap_or(domain);
+    !domain.is_empty()
+        && domain.len() <= 253
+        && domain
+            .split('.')
+            .all(|label| !label.is_empty() && label.len() <= 63)
+}
+
+/// UTS #46 ToUnicode. The domain is always converted; errors are reported
+/// alongside it.
+pub fn to_unicode(domain: &str, options: &Uts46Options) -> (String, Result<(), Uts46Error>) {
+    let (labels, error) = process(domain, options);
+    (labels.join("."), error.map_or(Ok(()), Err))
+}
+
+/// Joins labels in Unicode form, converting the non-ASCII ones to A-labels.
+fn encode_labels(labels: &[String]) -> Result<String, Uts46Error> {
+    let mut result = String::new();
+    for (i, label) in labels.iter().enumerate() {
+        if i > 0 {
+            result.push('.');
+        }
+        if label.is_ascii() {
+            result.push_str(label);
+        } else {
+            result.push_str("xn--");
+            PUNYCODE
+                .encode_str(label, &mut result)
+                .map_err(|_| Uts46Error::Punycode)?;
+        }
+    }
+    Ok(result)
+}
+
+/// The Map, Normalize, Break and Convert/Validate steps: returns the
+/// labels in Unicode form and the first error.
+fn process(domain: &str, options: &Uts46Options) -> (Vec<String>, Option<Uts46Error>) {
+    let mut mapped = String::with_capacity(domain.len());
+    for c in domain.chars() {
+        map(c, options.transitional_processing, &mut mapped);
+    }
+    let normalized = normalize(&mapped);
+
+    let mut error = None;
+    let mut record = |e| {
+        error.get_or_insert(e);
+    };
+
+    // Labels with the flag telling whether they were decoded from Punycode
+    let mut labels = Vec::new();
+    for label in normalized.split('.') {
+        let Some(encoded) = label.strip_prefix("xn--") else {
+            labels.push((label.to_string(), false));
+            continue;
+        };
+        if !label.is_ascii() {
+            record(Uts46Error::Punycode);
+            labels.push((label.to_string(), false));
+            continue;
+        }
+        let mut decoded = String::new();
+        if PUNYCODE.decode_to_string(encoded, &mut decoded).is_err() {
+            if !options.ignore_invalid_punycode {
+                record(Uts46Error::Punycode);
+            }
+            labels.push((label.to_string(), false));
+            continue;
+        }
+        if decoded.is_ascii() {
+            record(Uts46Error::Punycode);
+        }
+        labels.push((decoded, true));
+    }
+
+    let is_bidi_domain = options.check_bidi
+        && labels.iter().any(|(label, _)| {
+            label
+                .chars()
+                .any(|c| matches!(bidi_class(c), BidiClass::R | BidiClass::AL | BidiClass::AN))
+        });
+    for (label, decoded) in &labels {
+        if let Err(e) = validate(label, *decoded, is_bidi_domain, options) {
+            record(e);
+        }
+    }
+
+    (labels.into_iter().map(|(label, _)| label).collect(), error)
+}
+
+/// The Validity Criteria of UTS #46, section 4.1.
+fn validate(
+    label: &str,
+    decoded: bool,
+    is_bidi_domain: bool,
+    options: &Uts46Options,
+) -> Result<(), Uts46Error> {
+    let Some(first) = label.chars().next() else {
+        return Ok(());
+    };
+    if decoded && normalize(label) != label {
+        return Err(Uts46Error::NotNfc);
+    }
+    if options.check_hyphens {
+        // Positions are counted in code points
+        let mut third_and_fourth = label.chars().skip(2);
+        if (third_and_fourth.next() == Some('-') && third_and_fourth.next() == Some('-'))
+            || label.starts_with('-')
+            || label.ends_with('-')
+        {
+            return Err(Uts46Error::Hyphens);
+        }
+    } else if options.reject_xn_prefix && label.starts_with("xn--") {
+        return Err(Uts46Error::Hyphens);
+    }
+    if is_mark(first) {
+        return Err(Uts46Error::LeadingCombiningMark);
+    }
+    for c in label.chars() {
+        let allowed = match status(c) {
+            Status::Valid => true,
+            // Decoded labels are checked for Nontransitional Processing
+            Status::Deviation => decoded || !options.transitional_processing,
+            _ => false,
+        };
+        let std3 = !options.use_std3_ascii_rules
+            || !c.is_ascii()
+            || c.is_ascii_lowercase()
+            || c.is_ascii_digit()
+            || c == '-';
+        // A decoded label may contain a full stop, which is valid
+        if !allowed || !std3 || c == '.' {
+            return Err(Uts46Error::DisallowedCodePoint);
+        }
+    }
+    if options.check_joiners && !passes_context_j(label) {
+        return Err(Uts46Error::ContextJ);
+    }
+    if is_bidi_domain && !passes_bidi_rule(label) {
+        return Err(Uts46Error::Bidi);
+    }
+    Ok(())
+}
+
+/// The CONTEXTJ rules of RFC 5892, Appendix A.1 and A.2.
+fn passes_context_j(label: &str) -> bool {
+    let chars: Vec<char> = label.chars().collect();
+    chars.iter().enumerate().all(|(i, &c)| {
+        if c != '\u{200C}' && c != '\u{200D}' {
+            return true;
+        }
+        const VIRAMA: u8 = 9;
+        if i > 0 && get_ccc(chars[i - 1] as u32) == VIRAMA {
+            return true;
+        }
+        if c == '\u{200D}' {
+            return false;
+        }
+        // (L | D) T* ZWNJ T* (R | D)
+        let joins = |c: &&char, types: [JoiningType; 2]| types.contains(&joining_type(**c));
+        let not_transparent = |c: &&char| joining_type(**c) != JoiningType::T;
+        chars[..i]
+            .iter()
+            .rev()
+            .find(not_transparent)
+            .is_some_and(|c| joins(&c, [JoiningType::L, JoiningType::D]))
+            && chars[i + 1..]
+                .iter()
+                .find(not_transparent)
+                .is_some_and(|c| joins(&c, [JoiningType::R, JoiningType::D]))
+    })
+}
+
+/// The Bidi rule of RFC 5893, section 2.
+fn passes_bidi_rule(label: &str) -> bool {
+    use BidiClass::*;
+    let classes: Vec<BidiClass> = label.chars().map(bidi_class).collect();
+    // The class of the last character that is not a nonspacing mark
+    let last = classes.iter().rev().find(|&&class| class != NSM);
+    match classes.first() {
+        Some(R | AL) => {
+            classes
+                .iter()
+                .all(|class| matches!(class, R | AL | AN | EN | ES | CS | ET | ON | BN | NSM))
+                && matches!(last, Some(R | AL | EN | AN))
+                && !(classes.contains(&EN) && classes.contains(&AN))
+        }
+        Some(L) => {
+            classes
+                .iter()
+                .all(|class| matches!(class, L | EN | ES | CS | ET | ON | BN | NSM))
+                && matches!(last, Some(L | EN))
+        }
+        _ => false,
+    }
+}
+
+enum Status {
+    Valid,
+    Ignored,
+    Disallowed,
+    Deviation,
+    Mapped(Mapping),
+}
+
+enum Mapping {
+    Char(char),
+    Chars(&'static [u32]),
+}
+
+/// Returns `true` if UTS #46 maps `c` to nothing.
+pub(crate) fn is_ignored(c: char) -> bool {
+    matches!(status(c), Status::Ignored)
+}
+
+fn status(c: char) -> Status {
+    let cp = c as u32;
+    let i = UTS46_MAPPING.partition_point(|&(start, _)| start <= cp) - 1;
+    let (start, value) = UTS46_MAPPING[i];
+    match value {
+        0 => Status::Valid,
+        1 => Status::Ignored,
+        2 => Status::Disallowed,
+        3 => Status::Deviation,
+        _ => {
+            let index = (value & 0x7FFF) as usize - 4;
+            let targets = &UTS46_MAPPING_TARGETS
+                [UTS46_MAPPING_OFFSETS[index] as usize..UTS46_MAPPING_OFFSETS[index + 1] as usize];
+            if value & 0x8000 != 0 {
+                let target = char::from_u32(targets[0] + (cp - start)).unwrap_or(c);
+                Status::Mapped(Mapping::Char(target))
+            } else {
+                Status::Mapped(Mapping::Chars(targets))
+            }
+        }
+    }
+}
+
+/// The Map step. Disallowed code points are kept, for validation to reject.
+fn map(c: char, transitional: bool, out: &mut String) {
+    match status(c) {
+        Status::Valid | Status::Disallowed => out.push(c),
+        Status::Ignored => {}
+        Status::Deviation if transitional => match c {
+            '\u{00DF}' => out.push_str("ss"),
+            '\u{03C2}' => out.push('\u{03C3}'),
+            _ => {}
+        },
+        Status::Deviation => out.push(c),
+        Status::Mapped(Mapping::Char(target)) => out.push(target),
+        Status::Mapped(Mapping::Chars(targets)) => {
+            out.extend(targets.iter().filter_map(|&cp| char::from_u32(cp)));
+        }
+    }
+}
+
+fn is_mark(c: char) -> bool {
+    let cp = c as u32;
+    MARKS
+        .binary_search_by(|&(first, last)| {
+            if last < cp {
+                core::cmp::Ordering::Less
+            } else if first > cp {
+                core::cmp::Ordering::Greater
+            } else {
+                core::cmp::Ordering::Equal
+            }
+        })
+        .is_ok()
+}
+
+/// The Bidi_Class values RFC 5893 distinguishes; every other class is
+/// `Other`.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum BidiClass {
+    L,
+    R,
+    AL,
+    EN,
+    ES,
+    ET,
+    AN,
+    CS,
+    NSM,
+    BN,
+    ON,
+    Other,
+}
+
+fn bidi_class(c: char) -> BidiClass {
+    const CLASSES: [BidiClass; 12] = [
+        BidiClass::L,
+        BidiClass::R,
+        BidiClass::AL,
+        BidiClass::EN,
+        BidiClass::ES,
+        BidiClass::ET,
+        BidiClass::AN,
+        BidiClass::CS,
+        BidiClass::NSM,
+        BidiClass::BN,
+        BidiClass::ON,
+        BidiClass::Other,
+    ];
+    let cp = c as u32;
+    let i = BIDI_CLASSES.partition_point(|&(start, _)| start <= cp) - 1;
+    CLASSES[BIDI_CLASSES[i].1 as usize]
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum JoiningType {
+    U,
+    L,
+    D,
+    R,
+    T,
+    C,
+}
+
+fn joining_type(c: char) -> JoiningType {
+    const TYPES: [JoiningType; 6] = [
+        JoiningType::U,
+        JoiningType::L,
+        JoiningType::D,
+        JoiningType::R,
+        JoiningType::T,
+        JoiningType::C,
+    ];
+    let cp = c as u32;
+    let i = JOINING_TYPES.partition_point(|&(start, _)| start <= cp) - 1;
+    TYPES[JOINING_TYPES[i].1 as usize]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapped(s: &str) -> String {
+        let mut out = String::new();
+        s.chars().for_each(|c| map(c, false, &mut out));
+        out
+    }
+
+    #[test]
+    fn test_map() {
+        assert_eq!(mapped("EXAMPLE"), "example");
+        assert_eq!(mapped("\u{FF27}\u{FF4F}\u{3002}com"), "go.com");
+        assert_eq!(mapped("\u{1D400}\u{1D419}"), "az");
+        assert_eq!(mapped("a\u{AD}\u{200B}b"), "ab");
+        assert_eq!(mapped("fa\u{DF}"), "fa\u{DF}");
+        assert_eq!(mapped("\u{2474}"), "(1)");
+        let mut transitional = String::new();
+        "fa\u{DF}\u{200D}"
+            .chars()
+            .for_each(|c| map(c, true, &mut transitional));
+        assert_eq!(transitional, "fass");
+    }
+
+    #[test]
+    fn test_properties() {
+        assert!(is_mark('\u{301}'));
+        assert!(is_mark('\u{903}'));
+        assert!(!is_mark('a'));
+        assert!(bidi_class('a') == BidiClass::L);
+        assert!(bidi_class('\u{5D0}') == BidiClass::R);
+        assert!(bidi_class('\u{627}') == BidiClass::AL);
+        assert!(bidi_class('\u{660}') == BidiClass::AN);
+        assert!(bidi_class(' ') == BidiClass::Other);
+        assert!(joining_type('\u{628}') == JoiningType::D);
+        assert!(joining_type('\u{627}') == JoiningType::R);
+        assert!(joining_type('\u{64B}') == JoiningType::T);
+        assert!(joining_type('a') == JoiningType::U);
+    }
+
+    #[test]
+    fn test_validity() {
+        let options = Uts46Options::default();
+        let check = |domain: &str| to_ascii(domain, &options);
+        assert_eq!(check("fa\u{DF}.de").as_deref(), Ok("xn--fa-hia.de"));
+        assert_eq!(check("a-.b"), Err(Uts46Error::Hyphens));
+        assert_eq!(check("ab--c"), Err(Uts46Error::Hyphens));
+        assert_eq!(check("a\u{E9}--b"), Err(Uts46Error::Hyphens));
+        assert_eq!(check("\u{E9}--x").as_deref(), Ok("xn----x-9la"));
+        assert_eq!(check("\u{301}a"), Err(Uts46Error::LeadingCombiningMark));
+        assert_eq!(check("a_b"), Err(Uts46Error::DisallowedCodePoint));
+        assert_eq!(check("a\u{FFFD}"), Err(Uts46Error::DisallowedCodePoint));
+        assert_eq!(check("xn--zz"), Err(Uts46Error::Punycode));
+        assert_eq!(check("xn--ab-"), Err(Uts46Error::Punycode));
+        assert_eq!(check("xn--a"), Err(Uts46Error::DisallowedCodePoint));
+        // An A-label must decode to an NFC label of valid code points
+        assert_eq!(check("xn--a-xbb"), Err(Uts46Error::NotNfc));
+        assert_eq!(check("xn--fa-hia.de").as_deref(), Ok("xn--fa-hia.de"));
+        assert_eq!(check("a..b"), Err(Uts46Error::DnsLength));
+        assert_eq!(check("a.b.").as_deref(), Ok("a.b."));
+        assert_eq!(check(&"a".repeat(64)), Err(Uts46Error::DnsLength));
+
+        // CONTEXTJ: ZWNJ between dual-joining letters or after a virama
+        assert_eq!(check("a\u{200C}b"), Err(Uts46Error::ContextJ));
+        assert!(check("\u{628}\u{200C}\u{628}").is_ok());
+        assert!(check("\u{915}\u{94D}\u{200D}").is_ok());
+        assert_eq!(check("\u{915}\u{200D}"), Err(Uts46Error::ContextJ));
+
+        // The Bidi rule applies to every label of a Bidi domain name
+        assert!(check("\u{5D0}\u{5D1}.com").is_ok());
+        assert_eq!(check("1.\u{5D0}"), Err(Uts46Error::Bidi));
+        assert_eq!(check("\u{5D0}a"), Err(Uts46Error::Bidi));
+        assert_eq!(check("\u{5D0}1\u{660}"), Err(Uts46Error::Bidi));
+        assert!(check("1.a").is_ok());
+    }
+
+    #[test]
+    fn test_flags() {
+        let lenient = Uts46Options {
+            check_hyphens: false,
+            use_std3_ascii_rules: false,
+            verify_dns_length: false,
+            ..Uts46Options::default()
+        };
+        assert_eq!(to_ascii("a-.b_c..", &lenient).as_deref(), Ok("a-.b_c.."));
+        assert_eq!(to_ascii("xn--zz.b", &lenient), Err(Uts46Error::Punycode));
+        let ignore = Uts46Options {
+            ignore_invalid_punycode: true,
+            ..lenient
+        };
+        assert_eq!(to_ascii("xn--zz.b", &ignore), Err(Uts46Error::Hyphens));
+        // "xn--xn--a--gua" decodes to a label that begins with "xn--"
+        assert_eq!(
+            to_ascii("xn--xn--a--gua.pt", &lenient),
+            Err(Uts46Error::Hyphens)
+        );
+        let xn_prefix = Uts46Options {
+            reject_xn_prefix: false,
+            ..lenient
+        };
+        assert_eq!(
+            to_ascii("xn--xn--a--gua.pt", &xn_prefix).as_deref(),
+            Ok("xn--xn--a--gua.pt")
+        );
+        let transitional = Uts46Options {
+            transitional_processing: true,
+            ..Uts46Options::default()
+        };
+        assert_eq!(
+            to_ascii("fa\u{DF}.de", &transitional).as_deref(),
+            Ok("fass.de")
+        );
+        assert_eq!(
+            to_ascii("xn--fa-hia.de", &transitional).as_deref(),
+            Ok("xn--fa-hia.de")
+        );
+    }
+
+    #[test]
+    fn test_to_unicode() {
+        let options = Uts46Options::default();
+        assert_eq!(
+            to_unicode("XN--FA-HIA.De", &options),
+            ("fa\u{DF}.de".to_string(), Ok(()))
+        );
+        assert_eq!(
+            to_unicode("xn--zz.b\u{FF0E}c", &options),
+            ("xn--zz.b.c".to_string(), Err(Uts46Error::Punycode))
+        );
+    }
+}
@@ -1,24 +1,5 @@
-/// 256-byte lookup table for forbidden ASCII code points, matching C++ implementation.
-/// 1 = forbidden, 0 = allowed.
-static IS_FORBIDDEN_DOMAIN_CODE_POINT_TABLE: [u8; 256] = [
-    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
-    1, 0, 0, 1, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 1, 0, 1, 1,
-    1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 0,
-    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 1,
-    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
-    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
-    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
-    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
-];
-
-#[inline]
-fn is_forbidden_domain_char(cp: u32) -> bool {
-    if cp < 256 {
-        IS_FORBIDDEN_DOMAIN_CODE_POINT_TABLE[cp as usize] != 0
-    } else {
-        false
-    }
-}
+use crate::normalization::CanonicalDecomposition;
+use crate::script::{Script, ScriptSet, confusable_scripts, script_extensions};
 
 pub fn valid_name_code_point(cp: u32) -> bool {
     // Fast path for common ASCII
@@ -117,8 +98,35 @@ fn is_letter(cp: u32) -> bool {
     }
 }
 
+/// Returns the scripts in which `label` has a whole-script confusable
+/// (UTS #39, section 4): a single-script string in another script that looks
+/// the same, such as Latin "cex" for Cyrillic "сех".
+///
+/// Every character of `label` must have a look-alike in such a script;
+/// characters used by all scripts (digits, hyphens, ...) match any script.
+/// Mixed-script labels have no whole-script confusables by definition, and
+/// the result is empty.
+pub fn whole_script_confusables(label: &str) -> ScriptSet {
+    let mut own = ScriptSet::ALL;
+    let mut confusable = ScriptSet::ALL;
+    // Prototypes are defined on the NFD form
+    for c in CanonicalDecomposition::new(label.chars()).filter_map(char::from_u32) {
+        let scripts = script_extensions(c);
+        if scripts.contains(Script::COMMON) || scripts.contains(Script::INHERITED) {
+            continue;
+        }
+        own = own.intersection(&scripts);
+        confusable = confusable.intersection(&confusable_scripts(c));
+    }
+    if own.is_empty() || own == ScriptSet::ALL {
+        return ScriptSet::EMPTY;
+    }
+    confusable.difference(&own)
+}
+
 pub fn contains_forbidden_domain_code_point(input: &str) -> bool {
-    input.chars().any(|c| is_forbidden_domain_char(c as u32))
+    // Only ASCII code points are forbidden, so scanning bytes is enough
+    crate::ascii::allowed_prefix_len(input.as_bytes()) < input.len()
 }
 
 pub fn is_ascii(input: &str) -> bool {
@@ -136,11 +144,9 @@ pub fn is_label_valid(label: &str) -> bool {
 
     if let Some(stripped) = label.strip_prefix("xn--") {
         // 1. Verify punycode is valid and decode it
-        let decoded = crate::punycode::punycode_to_utf32(stripped);
-        if decoded.is_none() {
+        let Ok(decoded) = crate::punycode::punycode_to_utf32(stripped) else {
             return false;
-        }
-        let decoded = decoded.unwrap();
+        };
 
         // 2. If the decoded is all-ASCII, it should not have been encoded as punycode
         if decoded.iter().all(|&cp| cp < 0x80) {
@@ -148,13 +154,15 @@ pub fn is_label_valid(label: &str) -> bool {
         }
 
         // 3. Convert decoded UTF-32 to UTF-8 string for mapping and normalization
-        let decoded_utf8_bytes = crate::unicode::utf32_to_utf8(&decoded);
+        let Ok(decoded_utf8_bytes) = crate::unicode::utf32_to_utf8(&decoded) else {
+            return false;
+        };
         let decoded_utf8 = String::from_utf8_lossy(&decoded_utf8_bytes);
         let mapped = crate::mapping::map(&decoded_utf8);
         let normalized = crate::normalization::normalize(&mapped);
 
         // 4. Convert normalized string back to UTF-32 for comparison
-        let normalized_utf32 = crate::unicode::utf8_to_utf32(normalized.as_bytes());
+        let normalized_utf32: Vec<u32> = normalized.chars().map(u32::from).collect();
 
         // 5. Ensure the decoded label is unchanged after mapping and normalization
         if normalized_utf32 != decoded {
@@ -209,10 +217,68 @@ mod tests {
         assert!(!is_label_valid(""));
     }
 
+    #[test]
+    fn test_whole_script_confusables() {
+        let cyrillic: ScriptSet = [Script::CYRILLIC].into_iter().collect();
+        let latin: ScriptSet = [Script::LATIN].into_iter().collect();
+        // All-Cyrillic "сех" and "асе" look like Latin "cex" and "ace"
+        assert!(whole_script_confusables("\u{441}\u{435}\u{445}").contains(Script::LATIN));
+        assert!(whole_script_confusables("\u{430}\u{441}\u{435}").contains(Script::LATIN));
+        assert!(whole_script_confusables("ace").contains(Script::CYRILLIC));
+        assert!(whole_script_confusables("ace-1").contains(Script::CYRILLIC));
+        // "п" and "z" have no look-alike in the other script
+        assert_eq!(
+            whole_script_confusables("\u{43f}\u{440}\u{438}\u{43c}\u{435}\u{440}")
+                .intersection(&latin),
+            ScriptSet::EMPTY
+        );
+        assert_eq!(
+            whole_script_confusables("zoo").intersection(&cyrillic),
+            ScriptSet::EMPTY
+        );
+        assert!(!whole_script_confusables("ace").contains(Script::LATIN));
+        // Mixed-script and script-less labels
+        assert_eq!(whole_script_confusables("\u{430}ce"), ScriptSet::EMPTY);
+        assert_eq!(whole_script_confusables("123"), ScriptSet::EMPTY);
+        assert_eq!(whole_script_confusables(""), ScriptSet::EMPTY);
+    }
+
+    #[test]
+    fn test_latin1_is_not_forbidden() {
+        // U+0080..U+00FF are not forbidden domain code points, although
+        // their code point values are below 256
+        for c in ('\u{80}'..='\u{FF}').map(String::from) {
+            assert!(!contains_forbidden_domain_code_point(&c), "{c:?}");
+            assert!(!contains_forbidden_domain_code_point(&format!("caf{c}")));
+        }
+    }
+
     #[test]
     fn test_contains_forbidden_domain_code_point() {
         assert!(!contains_forbidden_domain_code_point("example.com"));
         assert!(contains_forbidden_domain_code_point("exam ple.com"));
         assert!(contains_forbidden_domain_code_point("example.com/path"));
+        assert!(!contains_forbidden_domain_code_point("café.example"));
+        assert!(contains_forbidden_domain_code_point("caf\u{e9}\u{7f}"));
+
+        // Forbidden domain code points per the URL Standard, at every offset
+        // of a string long enough to reach the vectorized paths
+        let forbidden = |c: char| c <= ' ' || "#%/:<>?@[\\]^|\u{7f}".contains(c);
+        for c in (0..=0x7F)
+            .chain([0x80, 0xA0, 0xFF, 0x3000])
+            .filter_map(char::from_u32)
+        {
+            for at in 0..40 {
+                let mut input: Vec<char> =
+                    "abcdefghijklmnopqrstuvwxyz0123456789-é.ü".chars().collect();
+                input[at] = c;
+                let input: String = input.into_iter().collect();
+                assert_eq!(
+                    contains_forbidden_domain_code_point(&input),
+                    forbidden(c),
+                    "{input:?}"
+                );
+            }
+        }
     }
 }
@@ -0,0 +1,272 @@
+//! A small non-validating XML reader, enough for LGR files (RFC 7940).
+//!
+//! It builds an element tree and supports attributes, character and entity
+//! references, CDATA sections and comments. Processing instructions and the
+//! document type declaration are skipped, and namespace prefixes are kept as
+//! part of the names.
+
+#[derive(Debug, Default)]
+pub(crate) struct Element {
+    pub(crate) name: String,
+    /// Byte offset of the start tag in the document.
+    pub(crate) position: usize,
+    pub(crate) attributes: Vec<(String, String)>,
+    pub(crate) children: Vec<Element>,
+    /// Text directly inside the element, concatenated.
+    pub(crate) text: String,
+}
+
+impl Element {
+    pub(crate) fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub(crate) fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|child| child.name == name)
+    }
+}
+
+/// Parses a document and returns its root element, or the byte offset of
+/// the first error.
+pub(crate) fn parse(document: &str) -> Result<Element, usize> {
+    let mut reader = Reader {
+        input: document.as_bytes(),
+        document,
+        pos: 0,
+    };
+    reader.skip_misc()?;
+    let root = reader.element()?;
+    reader.skip_misc()?;
+    if reader.pos != reader.input.len() {
+        return Err(reader.pos);
+    }
+    Ok(root)
+}
+
+struct Reader<'a> {
+    input: &'a [u8],
+    document: &'a str,
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn starts_with(&self, s: &str) -> bool {
+        self.input[self.pos..].starts_with(s.as_bytes())
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.input.len() && self.input[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    /// Moves past the next occurrence of `end`.
+    fn skip_past(&mut self, end: &str) -> Result<(), usize> {
+        match self.document[self.pos..].find(end) {
+            Some(i) => {
+                self.pos += i + end.len();
+                Ok(())
+            }
+            None => Err(self.pos),
+        }
+    }
+
+    /// Skips whitespace, comments, processing instructions and the document
+    /// type declaration.
+    fn skip_misc(&mut self) -> Result<(), usize> {
+        loop {
+            self.skip_whitespace();
+            if self.starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if self.starts_with("<?") {
+                self.skip_past("?>")?;
+            } else if self.starts_with("<!DOCTYPE") {
+                self.doctype()?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn doctype(&mut self) -> Result<(), usize> {
+        // The internal subset may contain '>', so track brackets
+        let mut depth = 0;
+        while self.pos < self.input.len() {
+            match self.input[self.pos] {
+                b'[' => depth += 1,
+                b']' => depth -= 1,
+                b'>' if depth == 0 => {
+                    self.pos += 1;
+                    return Ok(());
+                }
+                _ => {}
+            }
+            self.pos += 1;
+        }
+        Err(self.pos)
+    }
+
+    fn name(&mut self) -> Result<String, usize> {
+        let start = self.pos;
+        while self.pos < self.input.len()
+            && !self.input[self.pos].is_ascii_whitespace()
+            && !b"=/>".contains(&self.input[self.pos])
+        {
+            self.pos += 1;
+        }
+        if self.pos == start {
+            return Err(start);
+        }
+        Ok(self.document[start..self.pos].to_string())
+    }
+
+    fn element(&mut self) -> Result<Element, usize> {
+        let position = self.pos;
+        if !self.starts_with("<") {
+            return Err(self.pos);
+        }
+        self.pos += 1;
+        let mut element = Element {
+            name: self.name()?,
+            position,
+            ..Element::default()
+        };
+
+        loop {
+            self.skip_whitespace();
+            if self.starts_with("/>") {
+                self.pos += 2;
+                return Ok(element);
+            }
+            if self.starts_with(">") {
+                self.pos += 1;
+                break;
+            }
+            let key = self.name()?;
+            self.skip_whitespace();
+            if !self.starts_with("=") {
+                return Err(self.pos);
+            }
+            self.pos += 1;
+            self.skip_whitespace();
+            let quote = *self.input.get(self.pos).ok_or(self.pos)?;
+            if quote != b'"' && quote != b'\'' {
+                return Err(self.pos);
+            }
+            self.pos += 1;
+            let start = self.pos;
+            let len = self.input[start..]
+                .iter()
+                .position(|&b| b == quote)
+                .ok_or(start)?;
+            self.pos += len + 1;
+            let value = unescape(&self.document[start..start + len]).ok_or(start)?;
+            element.attributes.push((key, value));
+        }
+
+        loop {
+            if self.pos >= self.input.len() {
+                return Err(self.pos);
+            }
+            if self.starts_with("</") {
+                self.pos += 2;
+                let end = self.pos;
+                if self.name()? != element.name {
+                    return Err(end);
+                }
+                self.skip_whitespace();
+                if !self.starts_with(">") {
+                    return Err(self.pos);
+                }
+                self.pos += 1;
+                return Ok(element);
+            } else if self.starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if self.starts_with("<![CDATA[") {
+                let start = self.pos + "<![CDATA[".len();
+                self.skip_past("]]>")?;
+                element
+                    .text
+                    .push_str(&self.document[start..self.pos - "]]>".len()]);
+            } else if self.starts_with("<?") {
+                self.skip_past("?>")?;
+            } else if self.starts_with("<") {
+                element.children.push(self.element()?);
+            } else {
+                let start = self.pos;
+                let len = self.input[start..]
+                    .iter()
+                    .position(|&b| b == b'<')
+                    .unwrap_or(self.input.len() - start);
+                self.pos += len;
+                let text = unescape(&self.document[start..self.pos]).ok_or(start)?;
+                element.text.push_str(&text);
+            }
+        }
+    }
+}
+
+/// Replaces the predefined entities and character references in `text`.
+fn unescape(text: &str) -> Option<String> {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find('&') {
+        result.push_str(&rest[..i]);
+        let end = rest[i..].find(';')? + i;
+        let entity = &rest[i + 1..end];
+        let c = match entity {
+            "lt" => '<',
+            "gt" => '>',
+            "amp" => '&',
+            "quot" => '"',
+            "apos" => '\'',
+            _ => {
+                let number = entity.strip_prefix('#')?;
+                let value = match number.strip_prefix('x') {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                    None => number.parse().ok()?,
+                };
+                char::from_u32(value)?
+            }
+        };
+        result.push(c);
+        rest = &rest[end + 1..];
+    }
+    result.push_str(rest);
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let document = "<?xml version=\"1.0\"?>\n<!DOCTYPE lgr [<!ENTITY x \"y\">]>\n<!-- c -->\n\
+             <lgr xmlns='urn:x'><meta><version>1 &amp; 2</version>\
+             <description><![CDATA[<b>]]></description></meta>\
+             <data><char cp=\"0061\" tag=\"&#x41;&#66;\"/></data></lgr>";
+        let root = parse(document).unwrap();
+        assert_eq!(root.name, "lgr");
+        assert_eq!(root.attribute("xmlns"), Some("urn:x"));
+        let meta = root.child("meta").unwrap();
+        assert_eq!(meta.child("version").unwrap().text, "1 & 2");
+        assert_eq!(meta.child("description").unwrap().text, "<b>");
+        let data = root.child("data").unwrap();
+        assert_eq!(data.children[0].attribute("tag"), Some("AB"));
+        assert_eq!(data.children[0].position, document.find("<char").unwrap());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse("<a>").unwrap_err(), 3);
+        assert_eq!(parse("<a></b>").unwrap_err(), 5);
+        assert_eq!(parse("<a x=1/>").unwrap_err(), 5);
+        assert_eq!(parse("<a x='&bogus;'/>").unwrap_err(), 6);
+        assert_eq!(parse("<a/><b/>").unwrap_err(), 4);
+        assert!(parse("").is_err());
+    }
+}
@@ -0,0 +1,63 @@
+use ada_idna::{EncodeUtf8, Utf8CodePoints, decode_label, map_chars, normalize_chars};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn allocations_during(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    f();
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+#[test]
+fn test_char_pipeline_does_not_allocate() {
+    for label in [
+        "Example",
+        "Bücher",
+        "Cafe\u{0301}",
+        "STRAẞE",
+        "\u{1112}\u{1161}\u{11AB}\u{AD6D}\u{C5B4}",
+        "ελληνικά",
+        "例子",
+    ] {
+        let mut out = [0u8; 64];
+        let allocations = allocations_during(|| {
+            let chars = Utf8CodePoints::new(label.as_bytes()).map_while(Result::ok);
+            let bytes = EncodeUtf8::new(normalize_chars(map_chars(chars)));
+            for (slot, byte) in out.iter_mut().zip(bytes) {
+                *slot = byte;
+            }
+        });
+        assert_eq!(allocations, 0, "{label:?} allocated");
+    }
+}
+
+#[test]
+fn test_decode_label_does_not_allocate() {
+    for label in ["bcher-kva", "fiqs8s", "n3h", "ls8h", &"a".repeat(59)] {
+        let mut out = String::with_capacity(256);
+        let allocations = allocations_during(|| {
+            decode_label(label, &mut out).unwrap();
+        });
+        assert_eq!(allocations, 0, "{label:?} allocated");
+    }
+}
@@ -0,0 +1,32 @@
+//! Helpers shared by the integration tests.
+
+/// Replaces `\uXXXX` escapes of unpaired surrogates, which a Rust string
+/// cannot hold, with U+FFFD, as browsers do for URL input.
+pub fn replace_lone_surrogates(json: &str) -> String {
+    let surrogate_at = |i: usize| {
+        json.get(i..i + 6)
+            .filter(|escape| escape.starts_with("\\u"))
+            .and_then(|escape| u16::from_str_radix(&escape[2..], 16).ok())
+            .filter(|unit| (0xD800..0xE000).contains(unit))
+    };
+    let mut result = String::with_capacity(json.len());
+    let mut rest = 0;
+    let mut i = 0;
+    while let Some(offset) = json[i..].find("\\u") {
+        i += offset;
+        match surrogate_at(i) {
+            Some(0xD800..=0xDBFF) if surrogate_at(i + 6).is_some_and(|low| low >= 0xDC00) => {
+                i += 12;
+            }
+            Some(_) => {
+                result.push_str(&json[rest..i]);
+                result.push_str("\\uFFFD");
+                i += 6;
+                rest = i;
+            }
+            None => i += 2,
+        }
+    }
+    result.push_str(&json[rest..]);
+    result
+}
@@ -0,0 +1,322 @@
+use ada_idna::host::{Host, HostError, parse_host, url_domain_to_ascii, url_domain_to_unicode};
+use serde::Deserialize;
+use std::fs;
+
+#[derive(Deserialize)]
+struct UrlTestCase {
+    input: String,
+    base: Option<String>,
+    hostname: Option<String>,
+    #[serde(default)]
+    failure: bool,
+}
+
+/// Extracts the host of an absolute URL with an authority, and whether the
+/// scheme is special. This is not the URL parser: it does not strip leading
+/// and trailing C0 controls and spaces or remove tabs and newlines, knows
+/// nothing of `file` URLs or base URLs, and cannot tell a failing host from
+/// a failing port. Inputs that need any of these give the reason they are
+/// skipped.
+fn split_host(case: &UrlTestCase) -> Result<(&str, bool), &'static str> {
+    let input = case.input.as_str();
+    if case.base.is_some() {
+        return Err("relative to a base URL");
+    }
+    if input.contains(['\t', '\n', '\r']) {
+        return Err("tab or newline");
+    }
+    if input.starts_with(|c| c <= ' ') || input.ends_with(|c| c <= ' ') {
+        return Err("leading or trailing C0 control or space");
+    }
+    let (scheme, rest) = input.split_once("://").ok_or("no authority")?;
+    if scheme == "file" {
+        return Err("file URL");
+    }
+    if !scheme.starts_with(|c: char| c.is_ascii_lowercase()) {
+        return Err("scheme needs lowercasing");
+    }
+    let special = matches!(scheme, "http" | "https" | "ws" | "wss" | "ftp");
+    let end = rest
+        .find(|c| matches!(c, '/' | '?' | '#') || (special && c == '\\'))
+        .unwrap_or(rest.len());
+    let authority = &rest[..end];
+    let host_and_port = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    // A colon after the closing bracket of an IPv6 address starts the port
+    let port_search_start = host_and_port.rfind(']').unwrap_or(0);
+    let (host, port) = match host_and_port[port_search_start..].rfind(':') {
+        Some(i) => host_and_port.split_at(port_search_start + i),
+        None => (host_and_port, ""),
+    };
+    let port = port.strip_prefix(':').unwrap_or(port);
+    if !port.is_empty() && port.parse::<u16>().is_err() {
+        return Err("invalid port");
+    }
+    Ok((host, special))
+}
+
+/// Fixture entries `split_host` cannot handle, with the reason.
+const SKIPPED_HOST_CASES: &[(&str, &str)] = &[
+    ("http://example\t.\norg", "relative to a base URL"),
+    (
+        "\u{0}\u{1b}\u{4}\u{12} http://example.com/\u{1f} \r ",
+        "tab or newline",
+    ),
+    ("file://example:1/", "file URL"),
+    ("http://f:b/c", "relative to a base URL"),
+];
+
+#[test]
+fn test_host_cases() {
+    let data = fs::read_to_string("tests/fixtures/host_cases.json")
+        .expect("Failed to read host_cases.json fixture");
+    let raw: serde_json::Value = serde_json::from_str(&data).expect("Invalid JSON");
+
+    let mut skipped = Vec::new();
+    for item in raw.as_array().expect("Fixture is not a JSON array") {
+        // Skip comments (strings)
+        if item.is_string() {
+            continue;
+        }
+        let case: UrlTestCase = serde_json::from_value(item.clone()).expect("Invalid test case");
+        let (host, special) = match split_host(&case) {
+            Ok(split) => split,
+            Err(reason) => {
+                skipped.push((case.input.clone(), reason));
+                continue;
+            }
+        };
+        let result = parse_host(host, special);
+
+        if case.failure {
+            assert!(
+                result.is_err(),
+                "Expected failure for '{}', got {:?}",
+                case.input,
+                result
+            );
+        } else {
+            let expected = case.hostname.expect("Test case without hostname");
+            match result {
+                Ok(host) => assert_eq!(host.to_string(), expected, "Input: '{}'", case.input),
+                Err(error) => panic!("Failed to parse '{}': {:?}", case.input, error),
+            }
+        }
+    }
+
+    let expected: Vec<_> = SKIPPED_HOST_CASES
+        .iter()
+        .map(|&(input, reason)| (input.to_string(), reason))
+        .collect();
+    assert_eq!(skipped, expected);
+}
+
+#[test]
+fn test_split_host() {
+    let case = |input: &str, base: Option<&str>| UrlTestCase {
+        input: input.into(),
+        base: base.map(String::from),
+        hostname: None,
+        failure: false,
+    };
+    assert_eq!(
+        split_host(&case("http://u:p@[::1]:80/x", None)),
+        Ok(("[::1]", true))
+    );
+    assert_eq!(split_host(&case("sc://a\\b/", None)), Ok(("a\\b", false)));
+    assert_eq!(split_host(&case("http://a:b/", None)), Err("invalid port"));
+    assert_eq!(
+        split_host(&case("http://a:65536/", None)),
+        Err("invalid port")
+    );
+    assert_eq!(
+        split_host(&case(" http://a/", None)),
+        Err("leading or trailing C0 control or space")
+    );
+    assert_eq!(
+        split_host(&case("http://a\tb/", None)),
+        Err("tab or newline")
+    );
+    assert_eq!(split_host(&case("file://a/", None)), Err("file URL"));
+    assert_eq!(
+        split_host(&case("HTTP://a/", None)),
+        Err("scheme needs lowercasing")
+    );
+    assert_eq!(split_host(&case("sc:a", None)), Err("no authority"));
+    assert_eq!(
+        split_host(&case("//a/", Some("http://b/"))),
+        Err("relative to a base URL")
+    );
+}
+
+#[test]
+fn test_host_kinds() {
+    assert_eq!(
+        parse_host("EXAMPLE.com", true),
+        Ok(Host::Domain("example.com".into()))
+    );
+    assert_eq!(
+        parse_host("b\u{fc}cher.example", true),
+        Ok(Host::Domain("xn--bcher-kva.example".into()))
+    );
+    assert_eq!(parse_host("127.1", true), Ok(Host::Ipv4(0x7F00_0001)));
+    assert_eq!(
+        parse_host("[::1]", true),
+        Ok(Host::Ipv6([0, 0, 0, 0, 0, 0, 0, 1]))
+    );
+    assert_eq!(
+        parse_host("[::1]", false),
+        Ok(Host::Ipv6([0, 0, 0, 0, 0, 0, 0, 1]))
+    );
+    // Opaque hosts are not lowercased or parsed as addresses
+    assert_eq!(
+        parse_host("EXAMPLE.1", false),
+        Ok(Host::Opaque("EXAMPLE.1".into()))
+    );
+    assert_eq!(parse_host("", false), Ok(Host::Opaque(String::new())));
+}
+
+#[test]
+fn test_host_errors() {
+    assert_eq!(parse_host("", true), Err(HostError::HostMissing));
+    assert_eq!(parse_host("[::1", true), Err(HostError::Ipv6Unclosed));
+    assert_eq!(
+        parse_host("a b", false),
+        Err(HostError::HostInvalidCodePoint)
+    );
+    assert_eq!(
+        parse_host("a%", true),
+        Err(HostError::DomainInvalidCodePoint)
+    );
+    assert_eq!(parse_host("\u{ad}", true), Err(HostError::DomainToAscii));
+    assert_eq!(
+        parse_host("1.2.3.4.5", true),
+        Err(HostError::Ipv4TooManyParts)
+    );
+    assert_eq!(parse_host("a.1", true), Err(HostError::Ipv4NonNumericPart));
+    assert_eq!(
+        parse_host("1.256.3", true),
+        Err(HostError::Ipv4OutOfRangePart)
+    );
+    assert_eq!(
+        parse_host("1.2.65536", true),
+        Err(HostError::Ipv4OutOfRangePart)
+    );
+    assert_eq!(
+        parse_host("[:1]", true),
+        Err(HostError::Ipv6InvalidCompression)
+    );
+    assert_eq!(
+        parse_host("[1:2:3:4:5:6:7:8:9]", true),
+        Err(HostError::Ipv6TooManyPieces)
+    );
+    assert_eq!(
+        parse_host("[1::2::3]", true),
+        Err(HostError::Ipv6MultipleCompression)
+    );
+    assert_eq!(
+        parse_host("[1:]", true),
+        Err(HostError::Ipv6InvalidCodePoint)
+    );
+    assert_eq!(parse_host("[1:2]", true), Err(HostError::Ipv6TooFewPieces));
+    assert_eq!(
+        parse_host("[1:2:3:4:5:6:7:1.2.3.4]", true),
+        Err(HostError::Ipv4InIpv6TooManyPieces)
+    );
+    assert_eq!(
+        parse_host("[::01.2.3.4]", true),
+        Err(HostError::Ipv4InIpv6InvalidCodePoint)
+    );
+    assert_eq!(
+        parse_host("[::1.2.3.256]", true),
+        Err(HostError::Ipv4InIpv6OutOfRangePart)
+    );
+    assert_eq!(
+        parse_host("[::1.2.3]", true),
+        Err(HostError::Ipv4InIpv6TooFewParts)
+    );
+}
+
+#[test]
+fn test_url_domain_to_ascii() {
+    assert_eq!(
+        url_domain_to_ascii("EXAMPLE.com", false).as_deref(),
+        Ok("example.com")
+    );
+    assert_eq!(
+        url_domain_to_ascii("Fa\u{df}.de", false).as_deref(),
+        Ok("xn--fa-hia.de")
+    );
+    // Without beStrict, hyphens, underscores and empty labels are allowed
+    assert_eq!(
+        url_domain_to_ascii("-a_b..", false).as_deref(),
+        Ok("-a_b..")
+    );
+    assert_eq!(
+        url_domain_to_ascii("a_b", true),
+        Err(HostError::DomainToAscii)
+    );
+    assert_eq!(
+        url_domain_to_ascii("a..b", true),
+        Err(HostError::DomainToAscii)
+    );
+    assert_eq!(
+        url_domain_to_ascii(&"a".repeat(64), true),
+        Err(HostError::DomainToAscii)
+    );
+    assert!(url_domain_to_ascii(&"a".repeat(64), false).is_ok());
+    assert_eq!(
+        url_domain_to_ascii("\u{ad}", false),
+        Err(HostError::DomainToAscii)
+    );
+    assert_eq!(
+        url_domain_to_ascii("a\u{ff05}b", false),
+        Err(HostError::DomainInvalidCodePoint)
+    );
+    assert_eq!(
+        url_domain_to_ascii("xn--zz", false),
+        Err(HostError::DomainToAscii)
+    );
+}
+
+#[test]
+fn test_url_domain_to_unicode() {
+    assert_eq!(
+        url_domain_to_unicode("xn--fa-hia.EXAMPLE", false),
+        ("fa\u{df}.example".to_string(), Ok(()))
+    );
+    assert_eq!(
+        url_domain_to_unicode("a_b.xn--zz", false),
+        ("a_b.xn--zz".to_string(), Err(HostError::DomainToUnicode))
+    );
+    assert_eq!(
+        url_domain_to_unicode("a_b", true),
+        ("a_b".to_string(), Err(HostError::DomainToUnicode))
+    );
+}
+
+#[test]
+fn test_url_domain_to_ascii_matches_idna() {
+    for input in [
+        "example.com",
+        "Fa\u{df}.de",
+        "caf\u{e9}.example",
+        "cafe\u{301}.example",
+        "\u{ff27}\u{ff4f}.com",
+        "\u{4f60}\u{597d}.\u{4e16}\u{754c}",
+        // Compositions new in Unicode 16.0
+        "\u{16D67}\u{16D67}.com",
+        "\u{16D63}\u{16D67}.com",
+        "\u{105D2}\u{307}.com",
+        "\u{16D68}.com",
+        "\u{105C9}.com",
+        "xn--zz",
+    ] {
+        assert_eq!(
+            url_domain_to_ascii(input, false).ok(),
+            idna::domain_to_ascii(input).ok(),
+            "Input: {input:?}"
+        );
+    }
+}
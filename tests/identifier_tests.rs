@@ -1,7 +1,7 @@
 use ada_idna::{unicode, validation};
 
 fn verify_code_point(input: &str, first: bool, expected: bool) {
-    let code_points = unicode::utf8_to_utf32(input.as_bytes());
+    let code_points = unicode::utf8_to_utf32(input.as_bytes()).unwrap();
     assert!(!code_points.is_empty(), "Failed to convert: {}", input);
     let actual = if first {
         validation::valid_name_code_point_first_position(code_points[0])
@@ -0,0 +1,229 @@
+//! Differential tests of `idna_compat` against the `idna` crate.
+#![cfg(feature = "idna-compat")]
+
+mod common;
+
+use ada_idna::idna_compat::uts46::{AsciiDenyList, DnsLength, Hyphens, Uts46};
+use ada_idna::idna_compat::{self, Config};
+use common::replace_lone_surrogates;
+use std::fs;
+
+/// Inputs of IdnaTestV2 and the cases where the `idna` API departs from
+/// plain UTS #46.
+fn corpus() -> Vec<String> {
+    let data = fs::read_to_string("tests/fixtures/IdnaTestV2.json")
+        .expect("Failed to read IdnaTestV2.json fixture");
+    let raw: serde_json::Value =
+        serde_json::from_str(&replace_lone_surrogates(&data)).expect("Invalid JSON");
+    let mut inputs: Vec<String> = raw
+        .as_array()
+        .expect("Fixture is not a JSON array")
+        .iter()
+        .filter_map(|item| item.get("input")?.as_str().map(String::from))
+        .collect();
+    let long_label = "a".repeat(63);
+    let long_domain = [long_label.as_str(); 4].join(".");
+    inputs.extend(
+        [
+            "",
+            ".",
+            "example.com.",
+            "a..b",
+            "-a.b-",
+            "ab--c",
+            "xn--",
+            "xn--zz",
+            "XN--FA-HIA.DE",
+            "xn--fa-hia.de",
+            "xn--nxasmq6b.com",
+            "xn--1ug.de",
+            "\u{E9}--x",
+            "a\u{E9}--b",
+            "\u{E9}-.a",
+            "-\u{E9}.a",
+            "\u{E9}\u{E9}--x",
+            "a b.c",
+            "a_b",
+            "a%b",
+            "a\u{FF05}b",
+            "[::1]",
+            "user@host",
+            "a\u{7F}",
+            "\u{DF}\u{3C2}\u{200C}\u{200D}.de",
+            "\u{1E9E}.de",
+            "\u{5D0}1.a",
+            "1a.\u{5D0}",
+            &long_label,
+            &format!("{long_label}a"),
+            &long_domain,
+            &format!("{long_domain}."),
+        ]
+        .map(String::from),
+    );
+    inputs
+}
+
+#[test]
+fn test_domain_to_ascii() {
+    for input in corpus() {
+        assert_eq!(
+            idna_compat::domain_to_ascii(&input).ok(),
+            idna::domain_to_ascii(&input).ok(),
+            "domain_to_ascii({input:?})"
+        );
+        assert_eq!(
+            idna_compat::domain_to_ascii_strict(&input).ok(),
+            idna::domain_to_ascii_strict(&input).ok(),
+            "domain_to_ascii_strict({input:?})"
+        );
+        let deny_lists = [
+            (AsciiDenyList::EMPTY, idna::AsciiDenyList::EMPTY),
+            (AsciiDenyList::STD3, idna::AsciiDenyList::STD3),
+            (AsciiDenyList::URL, idna::AsciiDenyList::URL),
+        ];
+        for (ours, theirs) in deny_lists {
+            assert_eq!(
+                idna_compat::domain_to_ascii_cow(input.as_bytes(), ours).ok(),
+                idna::domain_to_ascii_cow(input.as_bytes(), theirs).ok(),
+                "domain_to_ascii_cow({input:?}, {ours:?})"
+            );
+        }
+    }
+}
+
+#[test]
+fn test_domain_to_unicode() {
+    for input in corpus() {
+        let (output, result) = idna_compat::domain_to_unicode(&input);
+        let (expected, expected_result) = idna::domain_to_unicode(&input);
+        assert_eq!(
+            result.is_ok(),
+            expected_result.is_ok(),
+            "domain_to_unicode({input:?})"
+        );
+        // `idna` marks errors with U+FFFD in the output
+        if result.is_ok() {
+            assert_eq!(output, expected, "domain_to_unicode({input:?})");
+        }
+    }
+}
+
+#[test]
+#[allow(deprecated)]
+fn test_config() {
+    let corpus = corpus();
+    for flags in 0..16 {
+        let flag = |i: u32| flags & (1 << i) != 0;
+        let ours = Config::default()
+            .use_std3_ascii_rules(flag(0))
+            .transitional_processing(flag(1))
+            .verify_dns_length(flag(2))
+            .check_hyphens(flag(3))
+            .use_idna_2008_rules(false);
+        let theirs = idna::Config::default()
+            .use_std3_ascii_rules(flag(0))
+            .transitional_processing(flag(1))
+            .verify_dns_length(flag(2))
+            .check_hyphens(flag(3))
+            .use_idna_2008_rules(false);
+        for input in &corpus {
+            assert_eq!(
+                ours.to_ascii(input).ok(),
+                theirs.to_ascii(input).ok(),
+                "{ours:?}.to_ascii({input:?})"
+            );
+            let (output, result) = ours.to_unicode(input);
+            let (expected, expected_result) = theirs.to_unicode(input);
+            assert_eq!(
+                result.is_ok(),
+                expected_result.is_ok(),
+                "{ours:?}.to_unicode({input:?})"
+            );
+            if result.is_ok() {
+                assert_eq!(output, expected, "{ours:?}.to_unicode({input:?})");
+            }
+        }
+    }
+}
+
+#[test]
+fn test_uts46() {
+    let hyphens = [
+        (Hyphens::Allow, idna::uts46::Hyphens::Allow),
+        (
+            Hyphens::CheckFirstLast,
+            idna::uts46::Hyphens::CheckFirstLast,
+        ),
+        (Hyphens::Check, idna::uts46::Hyphens::Check),
+    ];
+    let dns_lengths = [
+        (DnsLength::Ignore, idna::uts46::DnsLength::Ignore),
+        (
+            DnsLength::VerifyAllowRootDot,
+            idna::uts46::DnsLength::VerifyAllowRootDot,
+        ),
+        (DnsLength::Verify, idna::uts46::DnsLength::Verify),
+    ];
+    let ours = Uts46::new();
+    let theirs = idna::uts46::Uts46::new();
+    for input in corpus() {
+        let input = input.as_bytes();
+        for (our_hyphens, their_hyphens) in hyphens {
+            for (our_length, their_length) in dns_lengths {
+                assert_eq!(
+                    ours.to_ascii(input, AsciiDenyList::STD3, our_hyphens, our_length)
+                        .ok(),
+                    theirs
+                        .to_ascii(
+                            input,
+                            idna::AsciiDenyList::STD3,
+                            their_hyphens,
+                            their_length
+                        )
+                        .ok(),
+                    "to_ascii({:?}, {our_hyphens:?}, {our_length:?})",
+                    String::from_utf8_lossy(input)
+                );
+            }
+            assert_eq!(
+                ours.to_unicode(input, AsciiDenyList::URL, our_hyphens)
+                    .1
+                    .is_ok(),
+                theirs
+                    .to_unicode(input, idna::AsciiDenyList::URL, their_hyphens)
+                    .1
+                    .is_ok(),
+                "to_unicode({:?}, {our_hyphens:?})",
+                String::from_utf8_lossy(input)
+            );
+        }
+    }
+    // Ill-formed UTF-8 fails
+    assert!(
+        ours.to_ascii(
+            b"a\xFF",
+            AsciiDenyList::EMPTY,
+            Hyphens::Allow,
+            DnsLength::Ignore
+        )
+        .is_err()
+    );
+    assert!(
+        theirs
+            .to_ascii(
+                b"a\xFF",
+                idna::AsciiDenyList::EMPTY,
+                idna::uts46::Hyphens::Allow,
+                idna::uts46::DnsLength::Ignore
+            )
+            .is_err()
+    );
+}
+
+#[test]
+fn test_errors() {
+    let error = idna_compat::domain_to_ascii("xn--zz").unwrap_err();
+    assert_eq!(error.to_string(), "Errors");
+    let result: Result<(), idna_compat::Errors> = error.into();
+    assert!(result.is_err());
+}
@@ -0,0 +1,164 @@
+use ada_idna::lgr::{Disposition, Evaluation, Lgr, LgrError, Reason, VariantError};
+
+fn example() -> Lgr {
+    Lgr::from_file("tests/fixtures/lgr/example.xml").expect("Failed to load the LGR fixture")
+}
+
+fn han() -> Lgr {
+    Lgr::from_file("tests/fixtures/lgr/variants.xml").expect("Failed to load the LGR fixture")
+}
+
+fn disposition(lgr: &Lgr, label: &str) -> Disposition {
+    lgr.evaluate(label).disposition
+}
+
+#[test]
+fn test_repertoire() {
+    let lgr = example();
+    assert_eq!(disposition(&lgr, "example"), Disposition::Valid);
+    assert_eq!(disposition(&lgr, "fran\u{e7}ais"), Disposition::Valid);
+    assert_eq!(
+        lgr.evaluate("stra\u{df}e"),
+        Evaluation {
+            disposition: Disposition::Invalid,
+            reason: Reason::NotInRepertoire {
+                position: 4,
+                code_point: '\u{df}'
+            },
+        }
+    );
+    assert_eq!(disposition(&lgr, "Example"), Disposition::Invalid);
+}
+
+#[test]
+fn test_context_rules() {
+    let lgr = example();
+    // The sequence l·l is in the repertoire as a whole
+    assert_eq!(disposition(&lgr, "col\u{b7}legi"), Disposition::Valid);
+    assert_eq!(
+        lgr.evaluate("co\u{b7}legi").reason,
+        Reason::ContextRule {
+            position: 2,
+            rule: "catalan-middle-dot".into()
+        }
+    );
+    assert_eq!(disposition(&lgr, "nau\u{308}"), Disposition::Valid);
+    assert_eq!(
+        lgr.evaluate("n\u{308}").reason,
+        Reason::ContextRule {
+            position: 1,
+            rule: "after-vowel".into()
+        }
+    );
+}
+
+#[test]
+fn test_whole_label_evaluation() {
+    let lgr = example();
+    assert_eq!(lgr.evaluate("-abc").reason, Reason::Action(0));
+    assert_eq!(lgr.evaluate("abc-").reason, Reason::Action(0));
+    assert_eq!(lgr.evaluate("ab--c").reason, Reason::Action(1));
+    assert_eq!(disposition(&lgr, "a-b-c"), Disposition::Valid);
+    assert_eq!(
+        lgr.evaluate("2024"),
+        Evaluation {
+            disposition: Disposition::Blocked,
+            reason: Reason::Action(2),
+        }
+    );
+}
+
+#[test]
+fn test_evaluate_label() {
+    let lgr = example();
+    // A-labels are decoded by to_unicode first
+    let evaluation = lgr.evaluate_label("xn--bcher-kva").unwrap();
+    assert_eq!(evaluation.disposition, Disposition::Valid);
+    assert_eq!(
+        lgr.evaluate_label("example").unwrap().disposition,
+        Disposition::Valid
+    );
+    assert_eq!(
+        lgr.evaluate_label("xn--ao-zja").unwrap().disposition,
+        Disposition::Invalid
+    );
+    assert!(lgr.evaluate_label("a.b").is_err());
+}
+
+#[test]
+fn test_variants() {
+    let lgr = han();
+    assert_eq!(
+        disposition(&lgr, "\u{4e2d}\u{56fd}"),
+        Disposition::Allocatable
+    );
+
+    let variants = lgr.variants("\u{4e2d}\u{56fd}", 10).unwrap();
+    assert_eq!(variants.len(), 1);
+    assert_eq!(variants[0].u_label, "\u{4e2d}\u{570b}");
+    assert_eq!(
+        variants[0].a_label,
+        ada_idna::to_ascii("\u{4e2d}\u{570b}").unwrap()
+    );
+    assert_eq!(variants[0].disposition, Disposition::Allocatable);
+
+    // Mixing simplified and traditional forms is blocked
+    let variants: Vec<(String, Disposition)> = lgr
+        .variants("\u{4e2d}\u{56fd}\u{5b66}", 10)
+        .unwrap()
+        .into_iter()
+        .map(|variant| (variant.u_label, variant.disposition))
+        .collect();
+    assert_eq!(
+        variants,
+        [
+            ("\u{4e2d}\u{56fd}\u{5b78}".to_string(), Disposition::Blocked),
+            ("\u{4e2d}\u{570b}\u{5b66}".to_string(), Disposition::Blocked),
+            (
+                "\u{4e2d}\u{570b}\u{5b78}".to_string(),
+                Disposition::Allocatable
+            ),
+        ]
+    );
+
+    let variants = lgr.variants("\u{53f0}\u{4e2d}", 10).unwrap();
+    assert_eq!(variants.len(), 2);
+    assert!(
+        variants
+            .iter()
+            .all(|variant| variant.disposition == Disposition::Blocked)
+    );
+    assert_eq!(disposition(&lgr, "\u{53f0}\u{4e2d}"), Disposition::Valid);
+}
+
+#[test]
+fn test_variant_limit() {
+    let lgr = han();
+    assert_eq!(
+        lgr.variants("\u{4e2d}\u{56fd}\u{5b66}", 3).unwrap().len(),
+        3
+    );
+    assert_eq!(
+        lgr.variants("\u{4e2d}\u{56fd}\u{5b66}", 2),
+        Err(VariantError::TooManyVariants)
+    );
+    // 2^64 combinations overflow without being enumerated
+    let label = "\u{56fd}".repeat(64);
+    assert_eq!(
+        lgr.variants(&label, usize::MAX),
+        Err(VariantError::TooManyVariants)
+    );
+    assert!(matches!(
+        lgr.variants("a", 10),
+        Err(VariantError::InvalidLabel(_))
+    ));
+    assert!(lgr.variants("\u{4e2d}", 10).unwrap().is_empty());
+}
+
+#[test]
+fn test_missing_file() {
+    assert!(matches!(
+        Lgr::from_file("tests/fixtures/lgr/missing.xml"),
+        Err(LgrError::Io(_))
+    ));
+}
@@ -1,27 +1,30 @@
-use ada_idna::{punycode_to_utf32, utf8_to_utf32, utf32_to_punycode, utf32_to_utf8};
+use ada_idna::{
+    punycode_encoded_len, punycode_to_utf32, utf8_to_utf32, utf32_to_punycode, utf32_to_utf8,
+    verify_punycode,
+};
 use std::fs;
 
 fn check_punycode_roundtrip(utf8_string: &str, puny_string: &str) {
     // UTF-8 <=> UTF-32 roundtrip
-    let utf32 = utf8_to_utf32(utf8_string.as_bytes());
-    let tmp = String::from_utf8(utf32_to_utf8(&utf32)).unwrap();
+    let utf32 = utf8_to_utf32(utf8_string.as_bytes()).unwrap();
+    let tmp = String::from_utf8(utf32_to_utf8(&utf32).unwrap()).unwrap();
     assert_eq!(tmp, utf8_string, "bad utf-8 <==> utf-32 transcoding");
 
     // UTF-32 => Punycode
     let puny = utf32_to_punycode(&utf32);
-    assert!(puny.is_some(), "bad utf-32 => punycode transcoding");
+    assert!(puny.is_ok(), "bad utf-32 => punycode transcoding");
     let puny = puny.unwrap();
     assert_eq!(puny, puny_string, "punycode mismatch");
 
     // Punycode => UTF-32
     let utf32back = punycode_to_utf32(&puny);
-    assert!(utf32back.is_some(), "bad punycode => utf-32 transcoding");
+    assert!(utf32back.is_ok(), "bad punycode => utf-32 transcoding");
     let utf32back = utf32back.unwrap();
 
     // Roundtrip Punycode
     let punyback = utf32_to_punycode(&utf32back);
     assert!(
-        punyback.is_some(),
+        punyback.is_ok(),
         "bad utf-32 => punycode transcoding (second time)"
     );
     let punyback = punyback.unwrap();
@@ -31,7 +34,7 @@ fn check_punycode_roundtrip(utf8_string: &str, puny_string: &str) {
     );
 
     // Full roundtrip back to UTF-8
-    let finalutf8 = String::from_utf8(utf32_to_utf8(&utf32back)).unwrap();
+    let finalutf8 = String::from_utf8(utf32_to_utf8(&utf32back).unwrap()).unwrap();
     assert_eq!(
         finalutf8, utf8_string,
         "bad roundtrip utf8 => utf8 transcoding"
@@ -44,7 +47,7 @@ fn test_punycode_fixture_alternating() {
     let data = fs::read_to_string(fixture_path)
         .expect("Failed to read utf8_punycode_alternating.txt fixture");
 
-    for (i, line) in data.lines().enumerate() {
+    for line in data.lines() {
         // Skip comments and empty lines
         if line.trim().is_empty() || line.starts_with('#') || line.starts_with("idna-rust/") {
             continue;
@@ -58,3 +61,70 @@ fn test_punycode_fixture_alternating() {
         check_punycode_roundtrip(utf8, puny);
     }
 }
+
+#[test]
+fn test_punycode_encoder_matches_idna_crate() {
+    let pool: Vec<char> = "abcxyz019-éüßαβγабв例子测试ドメインตัวอย่าง\u{10348}\u{1F600}"
+        .chars()
+        .collect();
+    let mut state = 0x2545_f491_u32;
+    for len in (1..40).chain([100, 250, 1000]) {
+        for _ in 0..20 {
+            let input: String = (0..len)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 17;
+                    state ^= state << 5;
+                    pool[state as usize % pool.len()]
+                })
+                .collect();
+            let expected = idna::punycode::encode_str(&input);
+            let utf32 = utf8_to_utf32(input.as_bytes()).unwrap();
+            assert_eq!(
+                utf32_to_punycode(&utf32).ok(),
+                expected,
+                "encoding mismatch for {input:?}"
+            );
+            assert_eq!(
+                punycode_encoded_len(&utf32),
+                expected.as_ref().map(String::len),
+                "length mismatch for {input:?}"
+            );
+            if let Some(encoded) = expected {
+                assert_eq!(punycode_to_utf32(&encoded).as_ref(), Ok(&utf32));
+            }
+        }
+    }
+}
+
+#[test]
+fn test_verify_matches_decoder() {
+    // Every string of up to six symbols over an alphabet that reaches each
+    // error path: uppercase digits, a delimiter, a non-basic code point, a
+    // non-digit, and enough letters to spell the xn-- prefix.
+    let alphabet = ['a', 'A', 'x', 'n', '9', '-', 'é', '!'];
+    let mut inputs = vec![String::new()];
+    let mut previous = vec![String::new()];
+    for _ in 0..6 {
+        previous = previous
+            .iter()
+            .flat_map(|prefix| alphabet.iter().map(move |&c| format!("{prefix}{c}")))
+            .collect();
+        inputs.extend(previous.iter().cloned());
+    }
+    // Long runs of high digits overflow the 31-bit accumulators
+    for len in 1..16 {
+        for tail in ["", "a", "-9a", "a99"] {
+            inputs.push(format!("{}{tail}", "9".repeat(len)));
+            inputs.push(format!("ab-{}{tail}", "9".repeat(len)));
+        }
+    }
+
+    for input in &inputs {
+        assert_eq!(
+            verify_punycode(input),
+            punycode_to_utf32(input).is_ok(),
+            "verify/decode disagree on {input:?}"
+        );
+    }
+}
@@ -1,4 +1,7 @@
-use ada_idna::domain::{contains_forbidden_domain_code_point, to_ascii};
+mod common;
+
+use ada_idna::host::url_domain_to_ascii;
+use common::replace_lone_surrogates;
 use serde::Deserialize;
 use std::fs;
 
@@ -16,6 +19,7 @@ fn test_idna_test_v2_fixture() {
     let data = fs::read_to_string(fixture_path).expect("Failed to read IdnaTestV2.json fixture");
 
     // The fixture is a JSON array of objects and comments (strings)
+    let data = replace_lone_surrogates(&data);
     let raw: serde_json::Value = serde_json::from_str(&data).expect("Invalid JSON");
     let arr = raw.as_array().expect("Fixture is not a JSON array");
 
@@ -27,12 +31,7 @@ fn test_idna_test_v2_fixture() {
         let case: IdnaTestCase = serde_json::from_value(item.clone())
             .unwrap_or_else(|_| panic!("Failed to parse test case at index {}", i));
 
-        let output_cow = to_ascii(&case.input).unwrap_or_else(|_| String::new().into());
-        let output = if contains_forbidden_domain_code_point(&output_cow) {
-            String::new()
-        } else {
-            output_cow.into_owned()
-        };
+        let output = url_domain_to_ascii(&case.input, false).unwrap_or_default();
 
         match &case.output {
             Some(expected) => assert_eq!(